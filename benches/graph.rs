@@ -0,0 +1,63 @@
+//! Timings of graph-heavy operations on large generated specifications
+//!
+//! Run with `cargo bench --bench graph`.
+
+use std::{error, time};
+use conftool::display_vec::DisplayVec;
+use conftool::logger::Logger;
+use conftool::{manipulate, parse, validate, ConfigEntry, EntryType, Switch};
+
+/// Spec of `n` switches where option `i` depends on `i - 1` and `i / 2`,
+/// giving both long chains and wide fan-out
+fn spec(n: usize) -> Vec<ConfigEntry> {
+    (0..n).map(|i| {
+              let mut depends = vec![];
+              if i > 0 {
+                  depends.push(format!("CONFIG_OPT{}", i - 1));
+              }
+              if i > 2 {
+                  depends.push(format!("CONFIG_OPT{}", i / 2));
+              }
+              ConfigEntry {
+                  name: format!("CONFIG_OPT{}", i),
+                  depends: DisplayVec(depends),
+                  enttype: EntryType::Switch(Switch::No),
+                  choices: None,
+                  help: String::new()
+              }
+          })
+          .collect()
+}
+
+fn time<F, R>(name: &str, n: usize, f: F) -> R
+where
+    F: FnOnce() -> R
+{
+    let start = time::Instant::now();
+    let res = f();
+    println!("{:>6} options  {:32} {:>10.2?}", n, name, start.elapsed());
+    res
+}
+
+fn main() -> Result<(), Box<dyn error::Error>> {
+    let log = Logger::new(0);
+    for n in [1_000, 10_000, 50_000] {
+        let entries = spec(n);
+        let graph = time("build graph", n, || parse::spec_graph(&entries))?;
+        let last = entries.last().unwrap().name.as_str();
+        time("dependencies of deepest option", n, || graph.dependencies_of(&last))?;
+        time("dependents of root option", n, || graph.dependent_vertices(&"CONFIG_OPT0"))?;
+        time("direct edges of every option", n, || {
+            entries.iter().try_for_each(|e| graph.direct_dependencies_of(&e.name.as_str()).map(|_| ()))
+        })?;
+        if n > 10_000 {
+            continue;
+        }
+
+        let mut kvpairs = vec![];
+        time("enable deepest option", n, || manipulate::enable_option(last, &mut kvpairs, &entries, &graph, &log))?;
+        time("validate enabled config", n, || validate::validate_kvpairs(&kvpairs, &entries, &graph, &log))?;
+        time("disable root option", n, || manipulate::disable_option("CONFIG_OPT0", &mut kvpairs, &entries, &graph, &log))?;
+    }
+    Ok(())
+}
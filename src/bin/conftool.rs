@@ -1,6 +1,7 @@
 use std::process;
-use conftool::{cli, generate, list, logger, parse, manipulate, validate};
-use conftool::{ConfType, ListOp, Mode};
+use conftool::{cli, diagram, diff, generate, list, logger, menu, merge, parse, manipulate, specdiff, template, upgrade, validate, why};
+use conftool::{ListOp, Mode};
+use conftool::output::WriteMode;
 
 fn main() {
     let state = match cli::parse_args() {
@@ -13,6 +14,15 @@ fn main() {
 
     let log = logger::Logger::new(state.verbosity);
 
+    // Comparing specifications needs neither the default specification nor a config
+    if let Mode::SpecDiff { old, new, markdown } = &state.mode {
+        if let Err(err) = specdiff::print_spec_diff(old, new, *markdown, &log) {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+        return;
+    }
+
     if !state.spec.exists() {
         eprintln!("Specification {} does not exist", state.spec.display());
         process::exit(1);
@@ -26,7 +36,20 @@ fn main() {
         }
     }
 
-    let entries = parse::parse_spec(&state.spec).unwrap();
+    let entries = match parse::parse_spec(&state.spec) {
+        Ok(entries) => entries,
+        Err(err) => {
+            eprintln!("Invalid specification {}: {}", state.spec.display(), err);
+            process::exit(1);
+        }
+    };
+    let graph = match parse::spec_graph(&entries) {
+        Ok(graph) => graph,
+        Err(err) => {
+            eprintln!("Invalid specification {}: {}", state.spec.display(), err);
+            process::exit(1);
+        }
+    };
     let res = match state.mode {
         Mode::List { mut ops } => {
             loop {
@@ -40,19 +63,45 @@ fn main() {
                         list::show_all(&entries);
                         Ok(())
                     },
-                    ListOp::Dependencies(option) => list::dependencies(&option, &entries)
+                    ListOp::Dependencies(option) => list::dependencies(&option, &graph),
+                    ListOp::Dependents(option) => list::dependents(&option, &graph),
+                    ListOp::Tree(option, direction, depth) => list::tree(&option, direction, depth, &entries, &graph)
                 };
                 if res.is_err() {
                     break res;
                 }
             }
         },
-        Mode::Validate => validate::validate_config(&state.config, &entries, &log),
-        Mode::Enable { option } => manipulate::enable(&option, &state.config, &entries, &log),
-        Mode::Disable { option } => manipulate::disable(&option, &state.config, &entries, &log),
-        Mode::Set { option, value } => manipulate::set(&option, &value, &state.config, &entries, &log),
-        Mode::Generate { conftype } => match conftype {
-            ConfType::Defconfig => generate::defconfig(&state.config, &entries, &log)
+        Mode::Validate => validate::validate_config(&state.config, &entries, &graph, &log),
+        Mode::Enable { option, review } => manipulate::enable(&option, &state.config, review, &entries, &graph, &log),
+        Mode::Disable { option, review } => manipulate::disable(&option, &state.config, review, &entries, &graph, &log),
+        Mode::Set { option, value, review } =>
+            manipulate::set(&option, &value, &state.config, review, &entries, &graph, &log),
+        Mode::Generate { generator, output, check, params } => {
+            let registry = generate::Registry::default();
+            let mode = if check { WriteMode::Check } else { WriteMode::Write };
+            let invocation = generate::Invocation { output, mode, params };
+            generate::run(&registry, &generator, &state.config, &invocation, &entries, &graph, &log)
+        },
+        Mode::ListGenerators => {
+            generate::list(&generate::Registry::default());
+            Ok(())
+        },
+        Mode::Olddefconfig => upgrade::olddefconfig(&state.config, &entries, &graph, &log),
+        Mode::Oldconfig => upgrade::oldconfig(&state.config, &entries, &graph, &log),
+        Mode::Menu => menu::menu(&state.config, &entries, &graph, &log),
+        Mode::Merge { fragments, output } => {
+            let output = output.unwrap_or(state.config);
+            merge::merge(&fragments, &output, &entries, &graph, &log)
+        },
+        Mode::Diff { old, new, json } => diff::print_diff(&old, &new, json, &entries, &graph, &log),
+        Mode::SpecDiff { .. } => unreachable!("Specifications are compared before loading one"),
+        Mode::Why { option } => why::why(&option, &state.config, &entries, &graph, &log),
+        Mode::Graph { format, root, depth, values } =>
+            diagram::graph(format, root.as_deref(), depth, values, &state.config, &entries, &graph, &log),
+        Mode::Render { template, output, check } => {
+            let mode = if check { WriteMode::Check } else { WriteMode::Write };
+            template::render(&template, &output, &state.config, &entries, &graph, mode, &log)
         }
     };
 
@@ -0,0 +1,362 @@
+use std::{collections, error, path};
+use crate::generate::{Context, Generator, Resolved};
+use crate::logger::{Logger, Verbosity};
+use crate::{ConfigEntry, EntryType};
+
+#[derive(Debug, Clone, Copy)]
+enum Language {
+    Python,
+    Go,
+    Javascript
+}
+
+/// Literal representation of a resolved value
+#[derive(Debug)]
+enum Literal {
+    Bool(bool),
+    Int(i64),
+    Str(String),
+    Unset
+}
+
+impl Literal {
+    fn new(res: &Resolved<'_>) -> Result<Self, Box<dyn error::Error>> {
+        let value = match &res.value {
+            Some(value) => value,
+            None => return Ok(match res.entry.enttype {
+                EntryType::Switch(_) => Literal::Bool(false),
+                _ => Literal::Unset
+            })
+        };
+
+        match res.entry.enttype {
+            EntryType::Switch(_) => match value.as_str() {
+                "y" => Ok(Literal::Bool(true)),
+                "n" => Ok(Literal::Bool(false)),
+                _ => Err(format!("Invalid value \"{}\" for switch \"{}\"", value, res.entry.name).into())
+            },
+            EntryType::Int(_) => match value.trim().parse::<i64>() {
+                Ok(int) => Ok(Literal::Int(int)),
+                Err(_) => Err(format!("Invalid value \"{}\" for integer \"{}\"", value, res.entry.name).into())
+            },
+            EntryType::String(_) => Ok(Literal::Str(value.clone()))
+        }
+    }
+
+    fn render(&self, lang: Language) -> Option<String> {
+        match (self, lang) {
+            (Literal::Bool(true), Language::Python) => Some("True".to_string()),
+            (Literal::Bool(false), Language::Python) => Some("False".to_string()),
+            (Literal::Bool(b), _) => Some(b.to_string()),
+            (Literal::Int(i), _) => Some(i.to_string()),
+            // Json string escapes are valid in all supported languages
+            (Literal::Str(s), _) => Some(serde_json::to_string(s).unwrap()),
+            (Literal::Unset, Language::Python) => Some("None".to_string()),
+            (Literal::Unset, Language::Javascript) => Some("null".to_string()),
+            (Literal::Unset, Language::Go) => None
+        }
+    }
+}
+
+fn python_type(ent: &ConfigEntry, literal: &Literal) -> &'static str {
+    match (&ent.enttype, literal) {
+        (EntryType::Switch(_), _) => "bool",
+        (EntryType::Int(_), Literal::Unset) => "Optional[int]",
+        (EntryType::Int(_), _) => "int",
+        (EntryType::String(_), Literal::Unset) => "Optional[str]",
+        (EntryType::String(_), _) => "str"
+    }
+}
+
+const PYTHON_KEYWORDS: &[&str] = &[
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class", "continue",
+    "def", "del", "elif", "else", "except", "finally", "for", "from", "global", "if", "import", "in",
+    "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while", "with", "yield"
+];
+
+const GO_KEYWORDS: &[&str] = &[
+    "break", "case", "chan", "const", "continue", "default", "defer", "else", "fallthrough", "for",
+    "func", "go", "goto", "if", "import", "interface", "map", "package", "range", "return", "select",
+    "struct", "switch", "type", "var"
+];
+
+/// Turn an option name into a valid identifier by replacing invalid characters
+/// with `_`, prefixing names starting with a digit and suffixing keywords
+fn identifier(name: &str, lang: Language) -> String {
+    let mut ident: String = name.chars()
+                                .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+                                .collect();
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, '_');
+    }
+    let keywords = match lang {
+        Language::Python => PYTHON_KEYWORDS,
+        Language::Go => GO_KEYWORDS,
+        // Reserved words are valid property names
+        Language::Javascript => &[]
+    };
+    if keywords.contains(&ident.as_str()) {
+        ident.push('_');
+    }
+    ident
+}
+
+fn render(lang: Language, resolved: &[Resolved<'_>], log: &Logger) -> Result<String, Box<dyn error::Error>> {
+    let mut out = String::new();
+    match lang {
+        Language::Python => {
+            out.push_str("# Generated by conftool, do not edit\n");
+            out.push_str("from typing import Final, Optional\n");
+        },
+        Language::Go => {
+            out.push_str("// Code generated by conftool. DO NOT EDIT.\n\n");
+            out.push_str("package config\n\n");
+            out.push_str("const (\n");
+        },
+        Language::Javascript => {
+            out.push_str("// Generated by conftool, do not edit\n\n");
+            out.push_str("export const config = Object.freeze({\n");
+        }
+    };
+
+    let mut identifiers: collections::HashMap<String, &str> = collections::HashMap::new();
+    for res in resolved {
+        let name = identifier(&res.entry.name, lang);
+        if name != res.entry.name {
+            log.writeln(Verbosity::Lvl1, &format!("Emitting option \"{}\" as \"{}\"", res.entry.name, name));
+        }
+        if let Some(other) = identifiers.insert(name.clone(), &res.entry.name) {
+            return Err(format!("Options \"{}\" and \"{}\" both map to identifier \"{}\"", other, res.entry.name, name).into());
+        }
+        let literal = Literal::new(res)?;
+        let value = match literal.render(lang) {
+            Some(value) => value,
+            None => {
+                log.writeln(Verbosity::Lvl2, &format!("Omitting unset option \"{}\"", name));
+                continue;
+            }
+        };
+        log.writeln(Verbosity::Lvl3, &format!("Emitting {} = {}", name, value));
+
+        let help: Vec<&str> = res.entry.help.lines().collect();
+        match lang {
+            Language::Python => {
+                let mut doc = res.entry.help.replace('\\', "\\\\").replace("\"\"\"", "\\\"\\\"\\\"");
+                // A trailing quote would otherwise merge with the closing delimiter
+                if doc.ends_with('"') {
+                    doc.pop();
+                    doc.push_str("\\\"");
+                }
+                out.push_str(&format!("\n{}: Final[{}] = {}\n", name, python_type(res.entry, &literal), value));
+                out.push_str(&format!("\"\"\"{}\"\"\"\n", doc));
+            },
+            Language::Go => {
+                for (i, line) in help.iter().enumerate() {
+                    match i {
+                        0 => out.push_str(&format!("\t// {} {}\n", name, line)),
+                        _ => out.push_str(&format!("\t// {}\n", line))
+                    };
+                }
+                out.push_str(&format!("\t{} = {}\n", name, value));
+            },
+            Language::Javascript => {
+                out.push_str("    /**\n");
+                for line in &help {
+                    out.push_str(&format!("     * {}\n", line.replace("*/", "*\\/")));
+                }
+                out.push_str("     */\n");
+                out.push_str(&format!("    {}: {},\n", name, value));
+            }
+        };
+    }
+
+    match lang {
+        Language::Python => (),
+        Language::Go => out.push_str(")\n"),
+        Language::Javascript => {
+            out.push_str("});\n\n");
+            out.push_str("export default config;\n");
+        }
+    };
+
+    Ok(out)
+}
+
+fn write(lang: Language, ctx: &Context<'_>, output: &path::Path) -> Result<(), Box<dyn error::Error>> {
+    let contents = render(lang, ctx.resolved()?, ctx.log)?;
+    ctx.log.writeln(Verbosity::Lvl2, &format!("Writing {:?} bindings to {}", lang, output.display()));
+    ctx.write(output, &contents)?;
+    Ok(())
+}
+
+/// Python module with typed constants for the current config
+#[derive(Debug)]
+pub struct Python;
+
+impl Generator for Python {
+    fn name(&self) -> &str {
+        "python"
+    }
+
+    fn description(&self) -> &str {
+        "Python module with typed constants"
+    }
+
+    fn generate(&self, ctx: &Context<'_>, output: &path::Path) -> Result<(), Box<dyn error::Error>> {
+        write(Language::Python, ctx, output)
+    }
+}
+
+/// Go package with a `const` block for the current config
+#[derive(Debug)]
+pub struct Go;
+
+impl Generator for Go {
+    fn name(&self) -> &str {
+        "go"
+    }
+
+    fn description(&self) -> &str {
+        "Go package with a const block"
+    }
+
+    fn generate(&self, ctx: &Context<'_>, output: &path::Path) -> Result<(), Box<dyn error::Error>> {
+        write(Language::Go, ctx, output)
+    }
+}
+
+/// ES module exporting the current config as a frozen object
+#[derive(Debug)]
+pub struct Javascript;
+
+impl Generator for Javascript {
+    fn name(&self) -> &str {
+        "javascript"
+    }
+
+    fn description(&self) -> &str {
+        "ES module exporting a frozen object"
+    }
+
+    fn generate(&self, ctx: &Context<'_>, output: &path::Path) -> Result<(), Box<dyn error::Error>> {
+        write(Language::Javascript, ctx, output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bindings::*;
+    use crate::generate::Origin;
+    use crate::test_util::entry;
+    use crate::Switch;
+
+    fn documented(name: &str, enttype: EntryType, help: &str) -> ConfigEntry {
+        ConfigEntry { help: help.to_string(), ..entry(name, &[], enttype) }
+    }
+
+    fn entries() -> Vec<ConfigEntry> {
+        vec![
+            documented("CONFIG_FOO-BAR", EntryType::Switch(Switch::Yes), "Enable \"\"\"foo\"\"\" */ and bar"),
+            documented("CONFIG_PORT", EntryType::Int(80), "Port\nto listen on"),
+            documented("CONFIG_NAME", EntryType::String(String::new()), "Name ending in \""),
+            documented("CONFIG_UNSET", EntryType::String(String::new()), "")
+        ]
+    }
+
+    fn resolved(entries: &[ConfigEntry]) -> Vec<Resolved<'_>> {
+        let values = [Some("y"), Some("8080"), Some("a \"quoted\" \\ name"), None];
+        entries.iter()
+               .zip(values)
+               .map(|(entry, value)| Resolved {
+                   entry,
+                   value: value.map(|v| v.to_string()),
+                   origin: Origin::Explicit
+               })
+               .collect()
+    }
+
+    #[test]
+    fn renders_python() -> Result<(), Box<dyn error::Error>> {
+        let entries = entries();
+        assert_eq!(render(Language::Python, &resolved(&entries), &Logger::new(0))?,
+                   "# Generated by conftool, do not edit\n\
+                    from typing import Final, Optional\n\
+                    \n\
+                    CONFIG_FOO_BAR: Final[bool] = True\n\
+                    \"\"\"Enable \\\"\\\"\\\"foo\\\"\\\"\\\" */ and bar\"\"\"\n\
+                    \n\
+                    CONFIG_PORT: Final[int] = 8080\n\
+                    \"\"\"Port\nto listen on\"\"\"\n\
+                    \n\
+                    CONFIG_NAME: Final[str] = \"a \\\"quoted\\\" \\\\ name\"\n\
+                    \"\"\"Name ending in \\\"\"\"\"\n\
+                    \n\
+                    CONFIG_UNSET: Final[Optional[str]] = None\n\
+                    \"\"\"\"\"\"\n");
+        Ok(())
+    }
+
+    #[test]
+    fn renders_go() -> Result<(), Box<dyn error::Error>> {
+        let entries = entries();
+        assert_eq!(render(Language::Go, &resolved(&entries), &Logger::new(0))?,
+                   "// Code generated by conftool. DO NOT EDIT.\n\
+                    \n\
+                    package config\n\
+                    \n\
+                    const (\n\
+                    \t// CONFIG_FOO_BAR Enable \"\"\"foo\"\"\" */ and bar\n\
+                    \tCONFIG_FOO_BAR = true\n\
+                    \t// CONFIG_PORT Port\n\
+                    \t// to listen on\n\
+                    \tCONFIG_PORT = 8080\n\
+                    \t// CONFIG_NAME Name ending in \"\n\
+                    \tCONFIG_NAME = \"a \\\"quoted\\\" \\\\ name\"\n\
+                    )\n");
+        Ok(())
+    }
+
+    #[test]
+    fn renders_javascript() -> Result<(), Box<dyn error::Error>> {
+        let entries = entries();
+        assert_eq!(render(Language::Javascript, &resolved(&entries), &Logger::new(0))?,
+                   "// Generated by conftool, do not edit\n\
+                    \n\
+                    export const config = Object.freeze({\n\
+                    \x20   /**\n\
+                    \x20    * Enable \"\"\"foo\"\"\" *\\/ and bar\n\
+                    \x20    */\n\
+                    \x20   CONFIG_FOO_BAR: true,\n\
+                    \x20   /**\n\
+                    \x20    * Port\n\
+                    \x20    * to listen on\n\
+                    \x20    */\n\
+                    \x20   CONFIG_PORT: 8080,\n\
+                    \x20   /**\n\
+                    \x20    * Name ending in \"\n\
+                    \x20    */\n\
+                    \x20   CONFIG_NAME: \"a \\\"quoted\\\" \\\\ name\",\n\
+                    \x20   /**\n\
+                    \x20    */\n\
+                    \x20   CONFIG_UNSET: null,\n\
+                    });\n\
+                    \n\
+                    export default config;\n");
+        Ok(())
+    }
+
+    #[test]
+    fn mangles_identifiers() {
+        assert_eq!(identifier("CONFIG_FOO-BAR.baz", Language::Go), "CONFIG_FOO_BAR_baz");
+        assert_eq!(identifier("9LIVES", Language::Javascript), "_9LIVES");
+        assert_eq!(identifier("None", Language::Python), "None_");
+        assert_eq!(identifier("type", Language::Go), "type_");
+
+        let entries = [entry("CONFIG_A-B", &[], EntryType::Switch(Switch::No)),
+                       entry("CONFIG_A_B", &[], EntryType::Switch(Switch::No))];
+        let resolved: Vec<Resolved<'_>> = entries.iter()
+                                                 .map(|entry| Resolved { entry, value: None, origin: Origin::Disabled })
+                                                 .collect();
+        assert!(render(Language::Python, &resolved, &Logger::new(0)).is_err());
+    }
+}
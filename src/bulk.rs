@@ -0,0 +1,260 @@
+use std::{error, path, time};
+use crate::generate::{self, Context, Generator};
+use crate::graph::{state, Graph};
+use crate::logger::Verbosity;
+use crate::{manipulate, ConfigEntry, EntryType};
+
+/// Probability of enabling a switch unless hinted otherwise
+const DEFAULT_PROBABILITY: f64 = 0.5;
+
+/// Small, seedable pseudo-random number generator (splitmix64)
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniformly distributed value in `[0, 1)`
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniformly distributed index in `[0, len)`
+    pub fn index(&mut self, len: usize) -> usize {
+        (self.next_u64() % len as u64) as usize
+    }
+}
+
+/// Whether all dependencies of `ent` are enabled in `kvpairs`
+fn dependencies_enabled(ent: &ConfigEntry, kvpairs: &[(String, String)], graph: &Graph<&str, state::Complete>)
+    -> Result<bool, Box<dyn error::Error>>
+{
+    let deps = graph.dependencies_of(&ent.name.as_str())?;
+    Ok(deps.iter().all(|d| kvpairs.iter().any(|(k, v)| k == d && v == "y")))
+}
+
+/// Build a config by visiting options in dependency order, skipping those whose
+/// dependencies are disabled, and writing it in specification order
+fn build<F>(ctx: &Context<'_>, output: &path::Path, mut choose: F) -> Result<(), Box<dyn error::Error>>
+where
+    F: FnMut(&ConfigEntry) -> Result<String, Box<dyn error::Error>>
+{
+    let mut kvpairs: Vec<(String, String)> = Vec::with_capacity(ctx.entries.len());
+    for ent in generate::dependency_order(ctx.entries, ctx.graph)? {
+        if !dependencies_enabled(ent, &kvpairs, ctx.graph)? {
+            ctx.log.writeln(Verbosity::Lvl2, &format!("Skipping \"{}\" due to disabled dependencies", ent.name));
+            continue;
+        }
+        let value = choose(ent)?;
+        ctx.log.writeln(Verbosity::Lvl2, &format!("Choosing \"{}\" for option \"{}\"", value, ent.name));
+        kvpairs.push((ent.name.clone(), value));
+    }
+
+    kvpairs.sort_by_key(|(k, _)| ctx.entries.iter().position(|e| e.name == *k));
+    ctx.write(output, &manipulate::format_config(&kvpairs, ctx.log))?;
+    Ok(())
+}
+
+/// Config with every switch enabled
+#[derive(Debug)]
+pub struct Allyesconfig;
+
+impl Generator for Allyesconfig {
+    fn name(&self) -> &str {
+        "allyesconfig"
+    }
+
+    fn description(&self) -> &str {
+        "Config with every satisfiable switch enabled"
+    }
+
+    fn targets_config(&self) -> bool {
+        true
+    }
+
+    fn generate(&self, ctx: &Context<'_>, output: &path::Path) -> Result<(), Box<dyn error::Error>> {
+        build(ctx, output, |ent| Ok(match ent.enttype {
+            EntryType::Switch(_) => "y".to_string(),
+            _ => ent.default_value()
+        }))
+    }
+}
+
+/// Config with every switch disabled
+#[derive(Debug)]
+pub struct Allnoconfig;
+
+impl Generator for Allnoconfig {
+    fn name(&self) -> &str {
+        "allnoconfig"
+    }
+
+    fn description(&self) -> &str {
+        "Config with every switch disabled"
+    }
+
+    fn targets_config(&self) -> bool {
+        true
+    }
+
+    fn generate(&self, ctx: &Context<'_>, output: &path::Path) -> Result<(), Box<dyn error::Error>> {
+        build(ctx, output, |ent| Ok(match ent.enttype {
+            EntryType::Switch(_) => "n".to_string(),
+            _ => ent.default_value()
+        }))
+    }
+}
+
+/// Config with random valid values
+///
+/// Accepts the parameters `seed`, for reproducible output, and
+/// `probability:<OPTION>`, the probability in `[0, 1]` of enabling a
+/// particular switch. Options without choices keep their default.
+#[derive(Debug)]
+pub struct Randconfig;
+
+impl Randconfig {
+    fn seed(ctx: &Context<'_>) -> Result<u64, Box<dyn error::Error>> {
+        match ctx.param("seed") {
+            Some(seed) => match seed.parse::<u64>() {
+                Ok(seed) => Ok(seed),
+                Err(_) => Err(format!("Invalid seed \"{}\"", seed).into())
+            },
+            None => Ok(time::SystemTime::now().duration_since(time::UNIX_EPOCH)?.as_nanos() as u64)
+        }
+    }
+
+    fn probability(ctx: &Context<'_>, ent: &ConfigEntry) -> Result<f64, Box<dyn error::Error>> {
+        match ctx.param(&format!("probability:{}", ent.name)) {
+            Some(p) => match p.parse::<f64>() {
+                Ok(p) if (0.0..=1.0).contains(&p) => Ok(p),
+                _ => Err(format!("Invalid probability \"{}\" for option \"{}\"", p, ent.name).into())
+            },
+            None => Ok(DEFAULT_PROBABILITY)
+        }
+    }
+}
+
+impl Generator for Randconfig {
+    fn name(&self) -> &str {
+        "randconfig"
+    }
+
+    fn description(&self) -> &str {
+        "Config with random valid values, see --seed and --probability"
+    }
+
+    fn targets_config(&self) -> bool {
+        true
+    }
+
+    fn takes_params(&self) -> bool {
+        true
+    }
+
+    fn generate(&self, ctx: &Context<'_>, output: &path::Path) -> Result<(), Box<dyn error::Error>> {
+        for (key, _) in ctx.params {
+            let known = key == "seed" || key.strip_prefix("probability:")
+                                            .is_some_and(|opt| ctx.entries.iter().any(|e| e.name == opt));
+            if !known {
+                return Err(format!("Invalid randconfig parameter \"{}\"", key).into());
+            }
+        }
+
+        let seed = Randconfig::seed(ctx)?;
+        ctx.log.writeln(Verbosity::Lvl1, &format!("Using randconfig seed {}", seed));
+        let mut rng = Rng::new(seed);
+        build(ctx, output, |ent| {
+            let value = match (&ent.enttype, &ent.choices) {
+                (EntryType::Switch(_), _) => {
+                    match rng.next_f64() < Randconfig::probability(ctx, ent)? {
+                        true => "y".to_string(),
+                        false => "n".to_string()
+                    }
+                },
+                (_, Some(choices)) if !choices.is_empty() => choices[rng.index(choices.len())].clone(),
+                _ => ent.default_value()
+            };
+            Ok(value)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bulk::*;
+    use crate::test_util::{entry, with_choices};
+    use crate::{logger::Logger, output::WriteMode, parse, validate, Switch};
+    use std::{env, fs};
+
+    fn entries() -> Vec<ConfigEntry> {
+        vec![
+            with_choices(entry("CONFIG_LEVEL", &["CONFIG_DEBUG"], EntryType::String("info".to_string())), &["info", "trace"]),
+            entry("CONFIG_DEBUG", &["CONFIG_BASE"], EntryType::Switch(Switch::No)),
+            entry("CONFIG_BASE", &[], EntryType::Switch(Switch::Yes)),
+            entry("CONFIG_PORT", &[], EntryType::Int(80))
+        ]
+    }
+
+    fn generate(gen: &dyn Generator, params: &[(String, String)], name: &str) -> Result<String, Box<dyn error::Error>> {
+        let entries = entries();
+        let log = Logger::new(0);
+        let graph = parse::spec_graph(&entries)?;
+        let resolved = generate::resolve(&[], &entries, &graph, &log)?;
+        let ctx = Context::with_resolved(resolved, &entries, &graph, WriteMode::Write, params, &log);
+        let path = env::temp_dir().join(format!("conftool-bulk-{}-{}", std::process::id(), name));
+        gen.generate(&ctx, &path)?;
+        validate::validate_config(&path, &entries, &graph, &log)?;
+        let contents = fs::read_to_string(&path)?;
+        fs::remove_file(&path)?;
+        Ok(contents)
+    }
+
+    #[test]
+    fn allyesconfig_enables_all_switches() -> Result<(), Box<dyn error::Error>> {
+        let config = generate(&Allyesconfig, &[], "allyes")?;
+        assert_eq!(config, "CONFIG_LEVEL = info\nCONFIG_DEBUG = y\nCONFIG_BASE  = y\nCONFIG_PORT  = 80\n");
+        Ok(())
+    }
+
+    #[test]
+    fn allnoconfig_disables_all_switches() -> Result<(), Box<dyn error::Error>> {
+        let config = generate(&Allnoconfig, &[], "allno")?;
+        assert_eq!(config, "CONFIG_BASE = n\nCONFIG_PORT = 80\n");
+        Ok(())
+    }
+
+    #[test]
+    fn randconfig_is_reproducible_and_valid() -> Result<(), Box<dyn error::Error>> {
+        for seed in 0..32 {
+            let params = vec![("seed".to_string(), seed.to_string())];
+            let first = generate(&Randconfig, &params, "rand-a")?;
+            let second = generate(&Randconfig, &params, "rand-b")?;
+            assert_eq!(first, second);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn randconfig_honours_probability_hints() -> Result<(), Box<dyn error::Error>> {
+        let params = vec![("seed".to_string(), "7".to_string()),
+                          ("probability:CONFIG_BASE".to_string(), "1".to_string()),
+                          ("probability:CONFIG_DEBUG".to_string(), "0".to_string())];
+        let config = generate(&Randconfig, &params, "rand-hint")?;
+        assert!(config.contains("CONFIG_BASE  = y"));
+        assert!(config.contains("CONFIG_DEBUG = n"));
+        Ok(())
+    }
+}
@@ -0,0 +1,242 @@
+use std::{collections, error, fmt, io};
+use crate::diff::Difference;
+
+/// Why an option is part of a change set
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Reason {
+    /// The option the manipulation was asked to change
+    Requested,
+    /// Enabled or added because a changed option depends on it
+    Dependency,
+    /// Disabled or removed because an option it depends on was disabled
+    Dependent
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::Requested => write!(f, "requested"),
+            Reason::Dependency => write!(f, "pulled in as a dependency"),
+            Reason::Dependent => write!(f, "dependency disabled")
+        }
+    }
+}
+
+/// How the changes of a manipulation are reviewed before reaching the config
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Review {
+    /// Apply the changes right away
+    Apply,
+    /// Print the changes instead of applying them
+    DryRun,
+    /// Print the changes as JSON instead of applying them
+    Json,
+    /// Print the changes and apply them once confirmed
+    Confirm
+}
+
+/// Change to a single option
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct OptionChange {
+    pub option: String,
+    #[serde(flatten)]
+    pub difference: Difference,
+    pub reason: Reason
+}
+
+impl OptionChange {
+    /// Change of `option` from `old` to `new`, `None` meaning absent, or
+    /// nothing if both are the same
+    pub(crate) fn new(option: &str, old: Option<&str>, new: Option<String>, reason: Reason) -> Option<Self> {
+        let difference = match (old, new) {
+            (Some(old), Some(new)) if old != new => Difference::Changed { old: old.to_string(), new },
+            (None, Some(value)) => Difference::Added { value },
+            (Some(old), None) => Difference::Removed { value: old.to_string() },
+            _ => return None
+        };
+        Some(OptionChange { option: option.to_string(), difference, reason })
+    }
+
+    /// Value of the option after the change, `None` if it is removed
+    pub fn new_value(&self) -> Option<&str> {
+        match &self.difference {
+            Difference::Added { value } | Difference::Changed { new: value, .. } => Some(value),
+            Difference::Removed { .. } => None
+        }
+    }
+}
+
+impl fmt::Display for OptionChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.difference {
+            Difference::Added { value } => write!(f, "+ {} = {}", self.option, value)?,
+            Difference::Removed { value } => write!(f, "- {} = {}", self.option, value)?,
+            Difference::Changed { old, new } => write!(f, "~ {}: {} -> {}", self.option, old, new)?
+        };
+        write!(f, " ({})", self.reason)
+    }
+}
+
+/// Every change a manipulation makes to a config, to review before applying
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct ChangeSet {
+    changes: Vec<OptionChange>
+}
+
+impl ChangeSet {
+    pub(crate) fn new(changes: Vec<OptionChange>) -> Self {
+        ChangeSet { changes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &OptionChange> {
+        self.changes.iter()
+    }
+
+    /// Apply the changes to `kvpairs`, changing options in place and appending
+    /// added ones in change set order
+    pub fn apply(&self, kvpairs: &mut Vec<(String, String)>) {
+        let mut updates: collections::HashMap<&str, Option<&str>> = self.changes.iter()
+                                                                         .map(|c| (c.option.as_str(), c.new_value()))
+                                                                         .collect();
+        kvpairs.retain_mut(|(k, v)| match updates.remove(k.as_str()) {
+            Some(Some(value)) => {
+                *v = value.to_string();
+                true
+            },
+            Some(None) => false,
+            None => true
+        });
+        for change in &self.changes {
+            if let Some(Some(value)) = updates.remove(change.option.as_str()) {
+                kvpairs.push((change.option.clone(), value.to_string()));
+            }
+        }
+    }
+
+    /// Serialize the changes as a JSON array
+    pub fn to_json(&self) -> Result<String, Box<dyn error::Error>> {
+        Ok(serde_json::to_string_pretty(&self.changes)?)
+    }
+
+    /// Print the changes to `output` and ask whether to apply them, defaulting to no
+    ///
+    /// An empty change set is printed without asking.
+    pub fn confirm<R, W>(&self, input: &mut R, output: &mut W) -> io::Result<bool>
+    where
+        R: io::BufRead,
+        W: io::Write
+    {
+        write!(output, "{}", self)?;
+        if self.changes.is_empty() {
+            return Ok(false);
+        }
+        write!(output, "Apply these changes? [y/N]: ")?;
+        output.flush()?;
+
+        let mut answer = String::new();
+        input.read_line(&mut answer)?;
+        Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+    }
+}
+
+impl fmt::Display for ChangeSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.changes.is_empty() {
+            return writeln!(f, "No changes");
+        }
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::changeset::*;
+    use crate::test_util::{entry, kvpairs};
+    use crate::logger::Logger;
+    use crate::{manipulate, parse};
+    use crate::{ConfigEntry, EntryType, Switch};
+
+    fn entries() -> Vec<ConfigEntry> {
+        vec![
+            entry("CONFIG_BASE", &[], EntryType::Switch(Switch::No)),
+            entry("CONFIG_NET", &["CONFIG_BASE"], EntryType::Switch(Switch::No)),
+            entry("CONFIG_NET_PORT", &["CONFIG_NET"], EntryType::Int(80))
+        ]
+    }
+
+    #[test]
+    fn records_reasons_without_changing_the_config() -> Result<(), Box<dyn error::Error>> {
+        let entries = entries();
+        let graph = parse::spec_graph(&entries)?;
+        let log = Logger::new(0);
+        let config = kvpairs(&[("CONFIG_BASE", "n")]);
+        let changes = manipulate::set_changes("CONFIG_NET_PORT", "8080", &config, &entries, &graph, &log)?;
+        assert_eq!(config, kvpairs(&[("CONFIG_BASE", "n")]));
+        assert_eq!(changes.to_string(), "+ CONFIG_NET_PORT = 8080 (requested)\n\
+                                         ~ CONFIG_BASE: n -> y (pulled in as a dependency)\n\
+                                         + CONFIG_NET = y (pulled in as a dependency)\n");
+
+        let mut applied = config.clone();
+        changes.apply(&mut applied);
+        let mut expected = config;
+        manipulate::set_option("CONFIG_NET_PORT", "8080", &mut expected, &entries, &graph, &log)?;
+        assert_eq!(applied, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn serializes_cascades_as_json() -> Result<(), Box<dyn error::Error>> {
+        let entries = entries();
+        let graph = parse::spec_graph(&entries)?;
+        let config = kvpairs(&[("CONFIG_BASE", "y"), ("CONFIG_NET", "y"), ("CONFIG_NET_PORT", "80")]);
+        let changes = manipulate::disable_changes("CONFIG_BASE", &config, &entries, &graph, &Logger::new(0))?;
+        let reasons: Vec<Reason> = changes.iter().map(|c| c.reason).collect();
+        assert_eq!(reasons, vec![Reason::Requested, Reason::Dependent, Reason::Dependent]);
+
+        let json: serde_json::Value = serde_json::from_str(&changes.to_json()?)?;
+        assert_eq!(json[2], serde_json::json!({
+            "option": "CONFIG_NET_PORT",
+            "change": "removed",
+            "value": "80",
+            "reason": "dependent"
+        }));
+        let disabled = kvpairs(&[("CONFIG_BASE", "n")]);
+        assert!(manipulate::disable_changes("CONFIG_BASE", &disabled, &entries, &graph, &Logger::new(0))?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn unsetting_records_the_cascade() -> Result<(), Box<dyn error::Error>> {
+        let entries = entries();
+        let graph = parse::spec_graph(&entries)?;
+        let config = kvpairs(&[("CONFIG_BASE", "y"), ("CONFIG_NET", "y"), ("CONFIG_NET_PORT", "80")]);
+        // Without a value CONFIG_NET falls back to disabled, taking its dependent along
+        let changes = manipulate::unset_changes("CONFIG_NET", &config, &entries, &graph, &Logger::new(0))?;
+        assert_eq!(changes.to_string(), "- CONFIG_NET = y (requested)\n\
+                                         - CONFIG_NET_PORT = 80 (dependency disabled)\n");
+        Ok(())
+    }
+
+    #[test]
+    fn applies_only_when_confirmed() -> Result<(), Box<dyn error::Error>> {
+        let entries = entries();
+        let graph = parse::spec_graph(&entries)?;
+        let config = kvpairs(&[("CONFIG_BASE", "y")]);
+        let changes = manipulate::enable_changes("CONFIG_NET", &config, &entries, &graph, &Logger::new(0))?;
+
+        let mut output = vec![];
+        assert!(changes.confirm(&mut "y\n".as_bytes(), &mut output)?);
+        assert_eq!(String::from_utf8(output)?, "+ CONFIG_NET = y (requested)\nApply these changes? [y/N]: ");
+        assert!(!changes.confirm(&mut "\n".as_bytes(), &mut vec![])?);
+        assert!(!changes.confirm(&mut "".as_bytes(), &mut vec![])?);
+        Ok(())
+    }
+}
@@ -1,5 +1,5 @@
 use clap::{Parser, Subcommand};
-use crate::{ConfType, ListOp, Mode, State};
+use crate::{diagram, Direction, ListOp, Mode, Review, State};
 use std::error;
 use std::path;
 
@@ -45,31 +45,186 @@ enum Subcommands {
 
         /// List dependencies of option, including indirect ones
         #[clap(short, long = "dependencies", value_name = "OPTION")]
-        deps: Option<String>
+        deps: Option<String>,
+
+        /// List options depending on option, direct and transitive
+        #[clap(short = 'r', long, value_name = "OPTION")]
+        dependents: Option<String>,
+
+        /// Show dependencies or dependents as a tree of dependency paths
+        #[clap(short, long)]
+        tree: bool,
+
+        /// Maximum depth of the tree
+        #[clap(long, value_name = "N", requires = "tree")]
+        depth: Option<usize>
     },
     /// Validate config file
     Validate,
     /// Enable config options
     Enable {
         /// Option to enable, automatically handling dependencies
-        option: String
+        option: String,
+
+        /// Print the changes instead of writing them
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Print the changes as JSON instead of writing them
+        #[clap(long, conflicts_with = "dry-run")]
+        json: bool,
+
+        /// Ask for confirmation before writing the changes
+        #[clap(short, long, conflicts_with_all = &["dry-run", "json"])]
+        interactive: bool
     },
     /// Disable config options
     Disable {
         /// Option to disable
-        option: String
+        option: String,
+
+        /// Print the changes instead of writing them
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Print the changes as JSON instead of writing them
+        #[clap(long, conflicts_with = "dry-run")]
+        json: bool,
+
+        /// Ask for confirmation before writing the changes
+        #[clap(short, long, conflicts_with_all = &["dry-run", "json"])]
+        interactive: bool
     },
     /// Set config option
     Set {
         /// The option to set
         option: String,
         /// The value to assign the option
-        value: String
+        value: String,
+
+        /// Print the changes instead of writing them
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Print the changes as JSON instead of writing them
+        #[clap(long, conflicts_with = "dry-run")]
+        json: bool,
+
+        /// Ask for confirmation before writing the changes
+        #[clap(short, long, conflicts_with_all = &["dry-run", "json"])]
+        interactive: bool
     },
     /// Config generation
     Generate {
-        /// Type of config to generate
-        conftype: String
+        /// Generator to run, see --list
+        #[clap(required_unless_present = "list")]
+        conftype: Option<String>,
+
+        /// Output path, defaults to the config for config generators
+        #[clap(short, long, value_name = "OUTPUT")]
+        output: Option<String>,
+
+        /// List available generators
+        #[clap(short, long)]
+        list: bool,
+
+        /// Fail with a diff instead of writing if the output is out of date
+        #[clap(long)]
+        check: bool,
+
+        /// Seed for randconfig
+        #[clap(long, value_name = "SEED")]
+        seed: Option<u64>,
+
+        /// Probability of randconfig enabling a switch, may be passed repeatedly
+        #[clap(short, long, value_name = "OPTION=PROBABILITY", multiple_occurrences = true)]
+        probability: Vec<String>
+    },
+    /// Upgrade config to the current specification, filling in new options with defaults
+    Olddefconfig,
+    /// Interactively ask for values of options missing from the config
+    Oldconfig,
+    /// Browse and edit the config in a full-screen terminal interface
+    Menu,
+    /// Merge config fragments, later fragments overriding earlier ones
+    Merge {
+        /// Base config followed by fragments to merge into it
+        #[clap(required = true, min_values = 1)]
+        fragments: Vec<String>,
+
+        /// Path of the merged config, defaults to the config
+        #[clap(short, long, value_name = "OUTPUT")]
+        output: Option<String>
+    },
+    /// Compare two configs option by option
+    Diff {
+        /// Config to compare against
+        old: String,
+
+        /// Config to compare
+        new: String,
+
+        /// Print the differences as JSON
+        #[clap(long)]
+        json: bool
+    },
+    /// Compare two specifications and classify the changes
+    SpecDiff {
+        /// Previous specification
+        old: String,
+
+        /// New specification
+        new: String,
+
+        /// Print the changes as a Markdown changelog
+        #[clap(long)]
+        markdown: bool
+    },
+    /// Explain why an option has its current value
+    Why {
+        /// Option to explain
+        option: String
+    },
+    /// Print the dependency graph as a diagram
+    Graph {
+        /// Diagram format, dot or mermaid
+        #[clap(short, long, default_value = "dot", value_name = "FORMAT")]
+        format: String,
+
+        /// Only include options connected to this one
+        #[clap(long, value_name = "OPTION")]
+        root: Option<String>,
+
+        /// Maximum number of edges between the root and included options
+        #[clap(long, value_name = "N", requires = "root")]
+        depth: Option<usize>,
+
+        /// Label and colour options by their value in the config
+        #[clap(long)]
+        values: bool
+    },
+    /// Render template using the current config
+    Render {
+        /// Template to render
+        template: String,
+
+        /// Path of the rendered output
+        #[clap(short, long, value_name = "OUTPUT")]
+        output: String,
+
+        /// Fail with a diff instead of writing if the output is out of date
+        #[clap(long)]
+        check: bool
+    }
+}
+
+/// How to review changes given the manipulation flags, which clap keeps exclusive
+fn review(dry_run: bool, json: bool, interactive: bool) -> Review {
+    match (dry_run, json, interactive) {
+        (true, _, _) => Review::DryRun,
+        (_, true, _) => Review::Json,
+        (_, _, true) => Review::Confirm,
+        _ => Review::Apply
     }
 }
 
@@ -85,20 +240,29 @@ pub fn parse_args() -> Result<State, Box<dyn error::Error>> {
     };
 
     let mode = match args.subcmd {
-        Some(Subcommands::List { show, all, deps }) => {
+        Some(Subcommands::List { show, all, deps, dependents, tree, depth }) => {
             if all {
                 Some(Mode::List { ops: vec![ListOp::All] })
             }
             else {
-                match (&show, &deps) {
-                    (None, None) => None,
+                match (&show, &deps, &dependents) {
+                    (None, None, None) => None,
                     _ => {
                         let mut ops = vec![];
-                        if !show.is_none() {
-                            ops.push(ListOp::Show(show.unwrap()));
+                        if let Some(show) = show {
+                            ops.push(ListOp::Show(show));
+                        }
+                        if let Some(deps) = deps {
+                            ops.push(match tree {
+                                true => ListOp::Tree(deps, Direction::Dependencies, depth),
+                                false => ListOp::Dependencies(deps)
+                            });
                         }
-                        if !deps.is_none() {
-                            ops.push(ListOp::Dependencies(deps.unwrap()));
+                        if let Some(dependents) = dependents {
+                            ops.push(match tree {
+                                true => ListOp::Tree(dependents, Direction::Dependents, depth),
+                                false => ListOp::Dependents(dependents)
+                            });
                         }
                         Some(Mode::List { ops })
                     }
@@ -106,13 +270,63 @@ pub fn parse_args() -> Result<State, Box<dyn error::Error>> {
             }
         }
         Some(Subcommands::Validate) => Some(Mode::Validate),
-        Some(Subcommands::Enable { option }) => Some(Mode::Enable { option }),
-        Some(Subcommands::Disable { option }) => Some(Mode::Disable { option }),
-        Some(Subcommands::Set { option, value }) => Some(Mode::Set { option, value }),
-        Some(Subcommands::Generate { conftype }) => match conftype.as_ref() {
-                "defconfig" => Some(Mode::Generate { conftype: ConfType::Defconfig }),
-                _ => None
+        Some(Subcommands::Enable { option, dry_run, json, interactive }) =>
+            Some(Mode::Enable { option, review: review(dry_run, json, interactive) }),
+        Some(Subcommands::Disable { option, dry_run, json, interactive }) =>
+            Some(Mode::Disable { option, review: review(dry_run, json, interactive) }),
+        Some(Subcommands::Set { option, value, dry_run, json, interactive }) =>
+            Some(Mode::Set { option, value, review: review(dry_run, json, interactive) }),
+        Some(Subcommands::Generate { conftype, output, list, check, seed, probability }) => match (conftype, list) {
+            (_, true) => Some(Mode::ListGenerators),
+            (Some(generator), false) => {
+                let mut params = vec![];
+                if let Some(seed) = seed {
+                    params.push(("seed".to_string(), seed.to_string()));
+                }
+                for hint in probability {
+                    match hint.split_once('=') {
+                        Some((opt, p)) => params.push((format!("probability:{}", opt.trim()), p.trim().to_string())),
+                        None => return Err(format!("Invalid probability hint \"{}\", expected OPTION=PROBABILITY", hint).into())
+                    };
+                }
+                Some(Mode::Generate {
+                    generator,
+                    output: output.map(path::PathBuf::from),
+                    check,
+                    params
+                })
+            },
+            (None, false) => None
         },
+        Some(Subcommands::Olddefconfig) => Some(Mode::Olddefconfig),
+        Some(Subcommands::Oldconfig) => Some(Mode::Oldconfig),
+        Some(Subcommands::Menu) => Some(Mode::Menu),
+        Some(Subcommands::Merge { fragments, output }) => Some(Mode::Merge {
+            fragments: fragments.into_iter().map(path::PathBuf::from).collect(),
+            output: output.map(path::PathBuf::from)
+        }),
+        Some(Subcommands::Diff { old, new, json }) => Some(Mode::Diff {
+            old: path::PathBuf::from(old),
+            new: path::PathBuf::from(new),
+            json
+        }),
+        Some(Subcommands::SpecDiff { old, new, markdown }) => Some(Mode::SpecDiff {
+            old: path::PathBuf::from(old),
+            new: path::PathBuf::from(new),
+            markdown
+        }),
+        Some(Subcommands::Why { option }) => Some(Mode::Why { option }),
+        Some(Subcommands::Graph { format, root, depth, values }) => Some(Mode::Graph {
+            format: format.parse::<diagram::Format>()?,
+            root,
+            depth,
+            values
+        }),
+        Some(Subcommands::Render { template, output, check }) => Some(Mode::Render {
+            template: path::PathBuf::from(template),
+            output: path::PathBuf::from(output),
+            check
+        }),
         None => None
     };
     if mode.is_none() {
@@ -0,0 +1,296 @@
+use std::{error, fmt, fs, io, path};
+use crate::changeset::ChangeSet;
+use crate::generate::{self, Resolved};
+use crate::graph::{state, Graph};
+use crate::logger::Logger;
+use crate::{manipulate, parse, validate, ConfigEntry, EntryType};
+
+/// Typed value of an option
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Switch(bool),
+    String(String),
+    Int(i32)
+}
+
+impl Value {
+    /// Interpret `value` according to the type of `ent`
+    pub fn parse(value: &str, ent: &ConfigEntry) -> Result<Self, Box<dyn error::Error>> {
+        manipulate::validate_value(&ent.name, value, ent)?;
+        Ok(match ent.enttype {
+            EntryType::Switch(_) => Value::Switch(value == "y"),
+            EntryType::String(_) => Value::String(value.to_string()),
+            EntryType::Int(_) => Value::Int(value.trim().parse()?)
+        })
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Switch(_) => "switch",
+            Value::String(_) => "string",
+            Value::Int(_) => "integer"
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Switch(true) => write!(f, "y"),
+            Value::Switch(false) => write!(f, "n"),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Int(i) => write!(f, "{}", i)
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Switch(b)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(i: i32) -> Self {
+        Value::Int(i)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+/// Config held in memory and checked against a specification
+///
+/// Changes made through [`Config::set`], [`Config::enable`], [`Config::disable`]
+/// and [`Config::unset`] propagate to dependencies and dependents the same way
+/// the command line does, nothing is read from or written to disk unless asked.
+#[derive(Debug)]
+pub struct Config<'a> {
+    entries: &'a [ConfigEntry],
+    graph: &'a Graph<&'a str, state::Complete>,
+    kvpairs: Vec<(String, String)>,
+    log: Logger
+}
+
+impl<'a> Config<'a> {
+    /// Empty config for the specification `entries` with the dependency graph `graph`,
+    /// see [`parse::spec_graph`]
+    pub fn new(entries: &'a [ConfigEntry], graph: &'a Graph<&'a str, state::Complete>) -> Self {
+        Config { entries, graph, kvpairs: vec![], log: Logger::new(0) }
+    }
+
+    /// Parse config file contents, without validating options or values
+    pub fn parse(contents: &str, entries: &'a [ConfigEntry], graph: &'a Graph<&'a str, state::Complete>)
+        -> Result<Self, Box<dyn error::Error>>
+    {
+        let mut config = Config::new(entries, graph);
+        let lines: Vec<String> = contents.split('\n')
+                                         .map(|s| s.to_owned())
+                                         .collect();
+        validate::validate_line_format(&lines, &config.log)?;
+        config.kvpairs = parse::parse_config(&path::PathBuf::new(), Some(lines))?;
+        Ok(config)
+    }
+
+    /// Read and parse a config, see [`Config::parse`]
+    pub fn from_reader<R>(mut reader: R, entries: &'a [ConfigEntry], graph: &'a Graph<&'a str, state::Complete>)
+        -> Result<Self, Box<dyn error::Error>>
+    where
+        R: io::Read
+    {
+        let mut contents = String::new();
+        reader.read_to_string(&mut contents)?;
+        Config::parse(&contents, entries, graph)
+    }
+
+    /// Read and parse the config file at `path`, see [`Config::parse`]
+    pub fn load(path: &path::Path, entries: &'a [ConfigEntry], graph: &'a Graph<&'a str, state::Complete>)
+        -> Result<Self, Box<dyn error::Error>>
+    {
+        Config::parse(&fs::read_to_string(path)?, entries, graph)
+    }
+
+    /// Log operations at the given verbosity level
+    pub fn with_verbosity(mut self, verbosity: usize) -> Self {
+        self.log = Logger::new(verbosity);
+        self
+    }
+
+    /// Specification the config is checked against
+    pub fn entries(&self) -> &'a [ConfigEntry] {
+        self.entries
+    }
+
+    /// Options set explicitly, in file order
+    pub fn kvpairs(&self) -> &[(String, String)] {
+        &self.kvpairs
+    }
+
+    fn entry(&self, opt: &str) -> Result<&'a ConfigEntry, Box<dyn error::Error>> {
+        match self.entries.iter().find(|e| e.name == opt) {
+            Some(ent) => Ok(ent),
+            None => Err(format!("Invalid config option \"{}\"", opt).into())
+        }
+    }
+
+    /// Format `value` for `opt`, checking that its type matches the specification
+    fn typed_value(&self, opt: &str, value: Value) -> Result<String, Box<dyn error::Error>> {
+        let ent = self.entry(opt)?;
+        if value.type_name() != ent.type_name() {
+            return Err(format!("Cannot set {} option \"{}\" to {} value \"{}\"",
+                               ent.type_name(), opt, value.type_name(), value).into());
+        }
+        Ok(value.to_string())
+    }
+
+    /// Value explicitly set for `opt`, `None` if it is not in the config
+    pub fn get(&self, opt: &str) -> Result<Option<Value>, Box<dyn error::Error>> {
+        let ent = self.entry(opt)?;
+        match self.kvpairs.iter().find(|(k, _)| k == opt) {
+            Some((_, v)) => Ok(Some(Value::parse(v, ent)?)),
+            None => Ok(None)
+        }
+    }
+
+    /// Every option with its effective value, falling back to the specification defaults
+    pub fn resolve(&self) -> Result<Vec<Resolved<'a>>, Box<dyn error::Error>> {
+        generate::resolve(&self.kvpairs, self.entries, self.graph, &self.log)
+    }
+
+    /// Set `opt` to `value`, adjusting other options to satisfy dependencies
+    pub fn set<V>(&mut self, opt: &str, value: V) -> Result<(), Box<dyn error::Error>>
+    where
+        V: Into<Value>
+    {
+        let value = self.typed_value(opt, value.into())?;
+        manipulate::set_option(opt, &value, &mut self.kvpairs, self.entries, self.graph, &self.log)
+    }
+
+    /// Enable switch `opt` along with its dependencies
+    pub fn enable(&mut self, opt: &str) -> Result<(), Box<dyn error::Error>> {
+        manipulate::enable_option(opt, &mut self.kvpairs, self.entries, self.graph, &self.log)
+    }
+
+    /// Disable switch `opt` along with its dependents
+    pub fn disable(&mut self, opt: &str) -> Result<(), Box<dyn error::Error>> {
+        manipulate::disable_option(opt, &mut self.kvpairs, self.entries, self.graph, &self.log)
+    }
+
+    /// Remove `opt` from the config along with its dependents
+    pub fn unset(&mut self, opt: &str) -> Result<(), Box<dyn error::Error>> {
+        manipulate::unset_option(opt, &mut self.kvpairs, self.entries, self.graph, &self.log)
+    }
+
+    /// Changes [`Config::set`] would make, without making them
+    pub fn set_changes<V>(&self, opt: &str, value: V) -> Result<ChangeSet, Box<dyn error::Error>>
+    where
+        V: Into<Value>
+    {
+        let value = self.typed_value(opt, value.into())?;
+        manipulate::set_changes(opt, &value, &self.kvpairs, self.entries, self.graph, &self.log)
+    }
+
+    /// Changes [`Config::enable`] would make, without making them
+    pub fn enable_changes(&self, opt: &str) -> Result<ChangeSet, Box<dyn error::Error>> {
+        manipulate::enable_changes(opt, &self.kvpairs, self.entries, self.graph, &self.log)
+    }
+
+    /// Changes [`Config::disable`] would make, without making them
+    pub fn disable_changes(&self, opt: &str) -> Result<ChangeSet, Box<dyn error::Error>> {
+        manipulate::disable_changes(opt, &self.kvpairs, self.entries, self.graph, &self.log)
+    }
+
+    /// Changes [`Config::unset`] would make, without making them
+    pub fn unset_changes(&self, opt: &str) -> Result<ChangeSet, Box<dyn error::Error>> {
+        manipulate::unset_changes(opt, &self.kvpairs, self.entries, self.graph, &self.log)
+    }
+
+    /// Apply changes reviewed beforehand
+    pub fn apply(&mut self, changes: &ChangeSet) {
+        changes.apply(&mut self.kvpairs);
+    }
+
+    /// Check options, values and dependencies against the specification
+    pub fn validate(&self) -> Result<(), Box<dyn error::Error>> {
+        validate::validate_kvpairs(&self.kvpairs, self.entries, self.graph, &self.log)
+    }
+
+    /// Write the config file contents to `writer`
+    pub fn write_to<W>(&self, mut writer: W) -> Result<(), Box<dyn error::Error>>
+    where
+        W: io::Write
+    {
+        writer.write_all(self.to_string().as_bytes())?;
+        Ok(())
+    }
+
+    /// Write the config to `path`, leaving the file untouched if its contents would not change
+    pub fn save(&self, path: &path::Path) -> Result<(), Box<dyn error::Error>> {
+        manipulate::write_config(&self.kvpairs, path, &self.log)
+    }
+}
+
+impl fmt::Display for Config<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", manipulate::format_config(&self.kvpairs, &self.log))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::*;
+    use crate::test_util::entry;
+    use crate::Switch;
+
+    fn entries() -> Vec<ConfigEntry> {
+        vec![
+            entry("CONFIG_BASE", &[], EntryType::Switch(Switch::No)),
+            entry("CONFIG_NET", &["CONFIG_BASE"], EntryType::Switch(Switch::No)),
+            entry("CONFIG_NET_PORT", &["CONFIG_NET"], EntryType::Int(80)),
+            entry("CONFIG_NAME", &[], EntryType::String("host".to_string()))
+        ]
+    }
+
+    #[test]
+    fn propagates_changes_in_memory() -> Result<(), Box<dyn error::Error>> {
+        let entries = entries();
+        let graph = parse::spec_graph(&entries)?;
+        let mut config = Config::parse("CONFIG_NAME = box\n", &entries, &graph)?;
+        config.set("CONFIG_NET_PORT", 8080)?;
+        assert_eq!(config.get("CONFIG_NET_PORT")?, Some(Value::Int(8080)));
+        assert_eq!(config.get("CONFIG_BASE")?, Some(Value::Switch(true)));
+        config.validate()?;
+
+        config.unset("CONFIG_NET")?;
+        assert_eq!(config.get("CONFIG_NET")?, None);
+        assert_eq!(config.get("CONFIG_NET_PORT")?, None);
+        assert_eq!(config.to_string(), "CONFIG_NAME = box\nCONFIG_BASE = y\n");
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_mistyped_values() -> Result<(), Box<dyn error::Error>> {
+        let entries = entries();
+        let graph = parse::spec_graph(&entries)?;
+        let mut config = Config::from_reader("CONFIG_NET_PORT = http\n".as_bytes(), &entries, &graph)?;
+        assert!(config.get("CONFIG_NET_PORT").is_err());
+        assert!(config.validate().is_err());
+        assert!(config.set("CONFIG_NAME", true).is_err());
+        assert!(config.get("CONFIG_MISSING").is_err());
+
+        let mut out = vec![];
+        config.disable("CONFIG_BASE")?;
+        config.write_to(&mut out)?;
+        assert_eq!(String::from_utf8(out)?, "CONFIG_BASE = n\n");
+        Ok(())
+    }
+}
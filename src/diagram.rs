@@ -0,0 +1,289 @@
+use std::{collections, error, path, str};
+use crate::graph::{state, Graph};
+use crate::logger::{Logger, Verbosity};
+use crate::{parse, ConfigEntry, Direction};
+
+/// Output format of a dependency diagram
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Dot,
+    Mermaid
+}
+
+impl str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dot" => Ok(Format::Dot),
+            "mermaid" => Ok(Format::Mermaid),
+            _ => Err(format!("Invalid graph format \"{}\", expected dot or mermaid", s))
+        }
+    }
+}
+
+/// State of an option in the current config
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeState {
+    /// Switch set to `y`, or other option set to the contained value
+    Enabled(String),
+    /// Switch set to `n`
+    Disabled,
+    /// Option missing from the config
+    Unset
+}
+
+impl NodeState {
+    fn class(&self) -> &'static str {
+        match self {
+            NodeState::Enabled(_) => "enabled",
+            NodeState::Disabled => "disabled",
+            NodeState::Unset => "unset"
+        }
+    }
+
+    fn colour(&self) -> &'static str {
+        match self {
+            NodeState::Enabled(_) => "#b7e4b7",
+            NodeState::Disabled => "#f4b6b6",
+            NodeState::Unset => "#e0e0e0"
+        }
+    }
+}
+
+/// State of every option in the specification given the config `kvpairs`
+pub fn node_states(kvpairs: &[(String, String)], entries: &[ConfigEntry]) -> Vec<(String, NodeState)> {
+    entries.iter()
+           .map(|ent| {
+               let state = match kvpairs.iter().find(|(k, _)| *k == ent.name) {
+                   Some((_, v)) if ent.is_switch() && v == "n" => NodeState::Disabled,
+                   Some((_, v)) => NodeState::Enabled(v.clone()),
+                   None => NodeState::Unset
+               };
+               (ent.name.clone(), state)
+           })
+           .collect()
+}
+
+/// Options within `depth` edges of `root` along its dependencies or along its
+/// dependents, or all options without a root, in specification order
+fn select<'a>(graph: &Graph<&'a str, state::Complete>, entries: &'a [ConfigEntry], root: Option<&str>,
+              depth: Option<usize>)
+    -> Result<Vec<&'a str>, Box<dyn error::Error>>
+{
+    let root = match root {
+        Some(root) => match entries.iter().find(|e| e.name == root) {
+            Some(ent) => ent.name.as_str(),
+            None => return Err(format!("Invalid config option \"{}\"", root).into())
+        },
+        None => return Ok(entries.iter().map(|e| e.name.as_str()).collect())
+    };
+
+    // Each direction is expanded on its own, so options only sharing a
+    // dependency with the root stay out
+    let mut selected = collections::HashSet::from([root]);
+    for direction in [Direction::Dependencies, Direction::Dependents] {
+        let mut frontier = vec![root];
+        let mut level = 0;
+        while !frontier.is_empty() && depth.is_none_or(|d| level < d) {
+            let mut next = vec![];
+            for opt in frontier {
+                let neighbours = match direction {
+                    Direction::Dependencies => graph.direct_dependencies_of(&opt)?,
+                    Direction::Dependents => graph.direct_dependents_of(&opt)?
+                };
+                for n in neighbours {
+                    if selected.insert(n) {
+                        next.push(n);
+                    }
+                }
+            }
+            frontier = next;
+            level += 1;
+        }
+    }
+
+    Ok(entries.iter()
+              .map(|e| e.name.as_str())
+              .filter(|name| selected.contains(name))
+              .collect())
+}
+
+fn escape_dot(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn escape_mermaid(s: &str) -> String {
+    s.replace('"', "#quot;")
+}
+
+/// Render the dependency graph, edges pointing from an option to its dependencies
+///
+/// With `states`, nodes are labelled with and coloured by their state in the config.
+pub fn render<'a>(format: Format, entries: &'a [ConfigEntry], graph: &Graph<&'a str, state::Complete>,
+                  root: Option<&str>, depth: Option<usize>, states: Option<&[(String, NodeState)]>, log: &Logger)
+    -> Result<String, Box<dyn error::Error>>
+{
+    let nodes = select(graph, entries, root, depth)?;
+    log.writeln(Verbosity::Lvl1, &format!("Rendering {} options", nodes.len()));
+    let included: collections::HashSet<&str> = nodes.iter().copied().collect();
+
+    let states: Option<collections::HashMap<&str, &NodeState>> =
+        states.map(|s| s.iter().map(|(k, state)| (k.as_str(), state)).collect());
+    let state_of = |opt: &str| states.as_ref().and_then(|s| s.get(opt).copied());
+    let label = |opt: &str| match state_of(opt) {
+        Some(NodeState::Enabled(value)) => format!("{} = {}", opt, value),
+        Some(NodeState::Disabled) => format!("{} = n", opt),
+        _ => opt.to_string()
+    };
+
+    let mut edges = vec![];
+    for &opt in &nodes {
+        for dep in graph.direct_dependencies_of(&opt)? {
+            if included.contains(dep) {
+                edges.push((opt, dep));
+            }
+        }
+    }
+
+    let mut out = String::new();
+    match format {
+        Format::Dot => {
+            out.push_str("digraph conftool {\n    rankdir=LR;\n    node [shape=box];\n");
+            for &opt in &nodes {
+                let style = match state_of(opt) {
+                    Some(state) => format!(", style=filled, fillcolor=\"{}\"", state.colour()),
+                    None => String::new()
+                };
+                out.push_str(&format!("    \"{}\" [label=\"{}\"{}];\n", escape_dot(opt), escape_dot(&label(opt)), style));
+            }
+            for (opt, dep) in &edges {
+                out.push_str(&format!("    \"{}\" -> \"{}\";\n", escape_dot(opt), escape_dot(dep)));
+            }
+            out.push_str("}\n");
+        },
+        Format::Mermaid => {
+            // Option names may be Mermaid keywords or contain punctuation, so
+            // nodes get generated IDs and the name only appears in labels
+            let ids: collections::HashMap<&str, String> = nodes.iter()
+                                                               .enumerate()
+                                                               .map(|(i, &opt)| (opt, format!("n{}", i)))
+                                                               .collect();
+            out.push_str("graph LR\n");
+            for &opt in &nodes {
+                out.push_str(&format!("    {}[\"{}\"]\n", ids[opt], escape_mermaid(&label(opt))));
+            }
+            for (opt, dep) in &edges {
+                out.push_str(&format!("    {} --> {}\n", ids[opt], ids[dep]));
+            }
+            if states.is_some() {
+                for state in [NodeState::Enabled(String::new()), NodeState::Disabled, NodeState::Unset] {
+                    let members: Vec<&str> = nodes.iter()
+                                                  .filter(|opt| state_of(opt).map(|s| s.class()) == Some(state.class()))
+                                                  .map(|opt| ids[opt].as_str())
+                                                  .collect();
+                    out.push_str(&format!("    classDef {} fill:{}\n", state.class(), state.colour()));
+                    if !members.is_empty() {
+                        out.push_str(&format!("    class {} {}\n", members.join(","), state.class()));
+                    }
+                }
+            }
+        }
+    };
+    Ok(out)
+}
+
+/// Print the dependency graph, coloured by the config at `path` if `values` is set
+#[allow(clippy::too_many_arguments)]
+pub fn graph(format: Format, root: Option<&str>, depth: Option<usize>, values: bool, path: &path::PathBuf,
+             entries: &[ConfigEntry], graph: &Graph<&str, state::Complete>, log: &Logger)
+    -> Result<(), Box<dyn error::Error>>
+{
+    let states = match (values, path.exists()) {
+        (true, true) => Some(node_states(&parse::parse_config(path, None)?, entries)),
+        (true, false) => Some(node_states(&[], entries)),
+        (false, _) => None
+    };
+    print!("{}", render(format, entries, graph, root, depth, states.as_deref(), log)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diagram::*;
+    use crate::test_util::entry;
+    use crate::{EntryType, Switch};
+
+    fn entries() -> Vec<ConfigEntry> {
+        let switch = |name, depends| entry(name, depends, EntryType::Switch(Switch::Yes));
+        vec![
+            switch("CONFIG_A", &[]),
+            switch("CONFIG_B", &["CONFIG_A"]),
+            switch("CONFIG_C", &["CONFIG_B"]),
+            switch("CONFIG_D", &[])
+        ]
+    }
+
+    #[test]
+    fn renders_dot_around_root() -> Result<(), Box<dyn error::Error>> {
+        let entries = entries();
+        let graph = parse::spec_graph(&entries)?;
+        let dot = render(Format::Dot, &entries, &graph, Some("CONFIG_B"), Some(1), None, &Logger::new(0))?;
+        assert_eq!(dot, "digraph conftool {\n    rankdir=LR;\n    node [shape=box];\n\
+                         \x20   \"CONFIG_A\" [label=\"CONFIG_A\"];\n\
+                         \x20   \"CONFIG_B\" [label=\"CONFIG_B\"];\n\
+                         \x20   \"CONFIG_C\" [label=\"CONFIG_C\"];\n\
+                         \x20   \"CONFIG_B\" -> \"CONFIG_A\";\n\
+                         \x20   \"CONFIG_C\" -> \"CONFIG_B\";\n}\n");
+        Ok(())
+    }
+
+    #[test]
+    fn renders_mermaid_with_states() -> Result<(), Box<dyn error::Error>> {
+        let entries = entries();
+        let config = vec![("CONFIG_A".to_string(), "y".to_string()), ("CONFIG_B".to_string(), "n".to_string())];
+        let states = node_states(&config, &entries);
+        let graph = parse::spec_graph(&entries)?;
+        let mermaid = render(Format::Mermaid, &entries, &graph, Some("CONFIG_C"), Some(1), Some(&states), &Logger::new(0))?;
+        assert!(mermaid.starts_with("graph LR\n    n0[\"CONFIG_B = n\"]\n    n1[\"CONFIG_C\"]\n"));
+        assert!(mermaid.contains("    n1 --> n0\n"));
+        assert!(mermaid.contains("    class n0 disabled\n"));
+        assert!(mermaid.contains("    class n1 unset\n"));
+        assert!(!mermaid.contains("CONFIG_A"));
+        Ok(())
+    }
+
+    #[test]
+    fn leaves_out_siblings_of_root() -> Result<(), Box<dyn error::Error>> {
+        let mut entries = entries();
+        entries.push(entry("CONFIG_E", &["CONFIG_A"], EntryType::Switch(Switch::Yes)));
+        let graph = parse::spec_graph(&entries)?;
+        let mermaid = render(Format::Mermaid, &entries, &graph, Some("CONFIG_B"), None, None, &Logger::new(0))?;
+        assert_eq!(mermaid, "graph LR\n\
+                             \x20   n0[\"CONFIG_A\"]\n\
+                             \x20   n1[\"CONFIG_B\"]\n\
+                             \x20   n2[\"CONFIG_C\"]\n\
+                             \x20   n1 --> n0\n\
+                             \x20   n2 --> n1\n");
+        Ok(())
+    }
+
+    #[test]
+    fn mermaid_ids_do_not_depend_on_option_names() -> Result<(), Box<dyn error::Error>> {
+        let switch = |name, depends| entry(name, depends, EntryType::Switch(Switch::Yes));
+        let entries = vec![switch("end", &[]), switch("net-port.v2", &["end"])];
+        let graph = parse::spec_graph(&entries)?;
+        let states = node_states(&[("end".to_string(), "n".to_string())], &entries);
+        let mermaid = render(Format::Mermaid, &entries, &graph, None, None, Some(&states), &Logger::new(0))?;
+        assert_eq!(mermaid, "graph LR\n\
+                             \x20   n0[\"end = n\"]\n\
+                             \x20   n1[\"net-port.v2\"]\n\
+                             \x20   n1 --> n0\n\
+                             \x20   classDef enabled fill:#b7e4b7\n\
+                             \x20   classDef disabled fill:#f4b6b6\n\
+                             \x20   class n0 disabled\n\
+                             \x20   classDef unset fill:#e0e0e0\n\
+                             \x20   class n1 unset\n");
+        Ok(())
+    }
+}
@@ -0,0 +1,165 @@
+use std::{error, fmt, path};
+use crate::generate::{self, Origin};
+use crate::graph::{state, Graph};
+use crate::logger::{Logger, Verbosity};
+use crate::{parse, ConfigEntry};
+
+/// How an option differs between two configs
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "change", rename_all = "lowercase")]
+pub enum Difference {
+    /// Option only has a value in the new config
+    Added {
+        value: String
+    },
+    /// Option only has a value in the old config
+    Removed {
+        value: String
+    },
+    /// Option has a different value in each config
+    Changed {
+        old: String,
+        new: String
+    }
+}
+
+/// Difference of a single option
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct OptionDiff {
+    pub option: String,
+    #[serde(flatten)]
+    pub difference: Difference,
+    /// Whether either config sets the option explicitly, as opposed to the
+    /// difference only arising from defaults
+    pub explicit: bool
+}
+
+impl fmt::Display for OptionDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.difference {
+            Difference::Added { value } => write!(f, "+ {} = {}", self.option, value),
+            Difference::Removed { value } => write!(f, "- {} = {}", self.option, value),
+            Difference::Changed { old, new } => write!(f, "~ {}: {} -> {}", self.option, old, new)
+        }
+    }
+}
+
+fn difference(old: Option<&str>, new: Option<&str>) -> Option<Difference> {
+    match (old, new) {
+        (Some(old), Some(new)) if old != new => Some(Difference::Changed { old: old.to_string(), new: new.to_string() }),
+        (None, Some(new)) => Some(Difference::Added { value: new.to_string() }),
+        (Some(old), None) => Some(Difference::Removed { value: old.to_string() }),
+        _ => None
+    }
+}
+
+/// Compare two configs option by option, taking specification defaults into account
+///
+/// Options are reported in specification order, followed by options unknown to
+/// the specification in the order they appear in the configs.
+pub fn diff(old: &[(String, String)], new: &[(String, String)], entries: &[ConfigEntry],
+            graph: &Graph<&str, state::Complete>, log: &Logger)
+    -> Result<Vec<OptionDiff>, Box<dyn error::Error>>
+{
+    let old_resolved = generate::resolve(old, entries, graph, log)?;
+    let new_resolved = generate::resolve(new, entries, graph, log)?;
+
+    let mut diffs = vec![];
+    for (o, n) in old_resolved.iter().zip(&new_resolved) {
+        if let Some(difference) = difference(o.effective_value(), n.effective_value()) {
+            log.writeln(Verbosity::Lvl2, &format!("Option \"{}\" differs", o.entry.name));
+            diffs.push(OptionDiff {
+                option: o.entry.name.clone(),
+                difference,
+                explicit: o.origin == Origin::Explicit || n.origin == Origin::Explicit
+            });
+        }
+    }
+
+    let unknown = old.iter()
+                     .chain(new)
+                     .map(|(k, _)| k)
+                     .filter(|k| !entries.iter().any(|e| e.name == **k));
+    for opt in unknown {
+        if diffs.iter().any(|d| d.option == *opt) {
+            continue;
+        }
+        log.writeln(Verbosity::Lvl1, &format!("Option \"{}\" is not in the specification", opt));
+        let value = |kvpairs: &[(String, String)]| kvpairs.iter().find(|(k, _)| k == opt).map(|(_, v)| v.clone());
+        let (old, new) = (value(old), value(new));
+        if let Some(difference) = difference(old.as_deref(), new.as_deref()) {
+            diffs.push(OptionDiff { option: opt.clone(), difference, explicit: true });
+        }
+    }
+    Ok(diffs)
+}
+
+/// Print the differences between the configs at `old` and `new`, grouped by
+/// whether they stem from explicit settings or only from defaults
+pub fn print_diff(old: &path::PathBuf, new: &path::PathBuf, json: bool, entries: &[ConfigEntry],
+                  graph: &Graph<&str, state::Complete>, log: &Logger)
+    -> Result<(), Box<dyn error::Error>>
+{
+    let diffs = diff(&parse::parse_config(old, None)?, &parse::parse_config(new, None)?, entries, graph, log)?;
+    let (explicit, default): (Vec<_>, Vec<_>) = diffs.into_iter().partition(|d| d.explicit);
+
+    if json {
+        let json = serde_json::json!({ "explicit": explicit, "default": default });
+        println!("{}", serde_json::to_string_pretty(&json)?);
+        return Ok(());
+    }
+
+    if explicit.is_empty() && default.is_empty() {
+        println!("Configs are equivalent");
+    }
+    for (heading, diffs) in [("Explicit", &explicit), ("Default", &default)] {
+        if diffs.is_empty() {
+            continue;
+        }
+        println!("{} differences:", heading);
+        for d in diffs {
+            println!("  {}", d);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diff::*;
+    use crate::test_util::{entry, kvpairs};
+    use crate::{EntryType, Switch};
+
+    fn entries() -> Vec<ConfigEntry> {
+        vec![
+            entry("CONFIG_NET", &[], EntryType::Switch(Switch::Yes)),
+            entry("CONFIG_NET_PORT", &["CONFIG_NET"], EntryType::Int(80)),
+            entry("CONFIG_DEBUG", &[], EntryType::Switch(Switch::No))
+        ]
+    }
+
+    #[test]
+    fn same_effective_values_are_equivalent() -> Result<(), Box<dyn error::Error>> {
+        let old = kvpairs(&[("CONFIG_NET", "y"), ("CONFIG_NET_PORT", "80")]);
+        let new = kvpairs(&[("CONFIG_DEBUG", "n")]);
+        let entries = entries();
+        assert!(diff(&old, &new, &entries, &parse::spec_graph(&entries)?, &Logger::new(0))?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn separates_explicit_from_default_differences() -> Result<(), Box<dyn error::Error>> {
+        let old = kvpairs(&[("CONFIG_DEBUG", "y"), ("CONFIG_OLD", "1")]);
+        let new = kvpairs(&[("CONFIG_NET", "n")]);
+        let entries = entries();
+        let diffs = diff(&old, &new, &entries, &parse::spec_graph(&entries)?, &Logger::new(0))?;
+        assert_eq!(diffs.iter().map(|d| d.to_string()).collect::<Vec<_>>(), vec![
+            "~ CONFIG_NET: y -> n",
+            "- CONFIG_NET_PORT = 80",
+            "~ CONFIG_DEBUG: y -> n",
+            "- CONFIG_OLD = 1"
+        ]);
+        assert_eq!(diffs.iter().map(|d| d.explicit).collect::<Vec<_>>(), vec![true, false, true, true]);
+        Ok(())
+    }
+}
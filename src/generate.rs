@@ -1,19 +1,64 @@
-use std::{error, path};
+use std::{cell, collections, error, fmt, fs, path};
 use crate::logger::{Logger, Verbosity};
 use crate::graph::{state, Graph};
-use crate::{manipulate, ConfigEntry};
+use crate::output::{self, Outcome, WriteMode};
+use crate::{bindings, bulk, manipulate, parse, split, validate, ConfigEntry};
 
-pub fn defconfig(path: &path::PathBuf, entries: &[ConfigEntry], log: &Logger) -> Result<(), Box<dyn error::Error>> {
-    let mut kvpairs: Vec<(&str, String)> = Vec::with_capacity(entries.len());
-    let graph = Graph::<&str, state::Incomplete>::from(entries);
-    let graph = graph.into_complete()?;
+/// Where the value of a resolved option originates from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    /// Explicitly set in the config
+    Explicit,
+    /// Default value from the specification
+    Default,
+    /// Not set and unavailable due to a disabled dependency
+    Disabled
+}
+
+/// Option as seen after merging the config with the specification defaults
+#[derive(Debug, Clone)]
+pub struct Resolved<'a> {
+    /// Specification entry of the option
+    pub entry: &'a ConfigEntry,
+    /// Effective value, `None` if the option is disabled
+    pub value: Option<String>,
+    /// Where the value comes from
+    pub origin: Origin
+}
+
+impl Resolved<'_> {
+    /// Effective value, treating disabled switches as `n`
+    pub fn effective_value(&self) -> Option<&str> {
+        match (&self.value, self.entry.is_switch()) {
+            (Some(value), _) => Some(value),
+            (None, true) => Some("n"),
+            (None, false) => None
+        }
+    }
+}
 
-    let mut enable;
+/// Merge the supplied config with the defaults of the specification
+///
+/// Options present in `kvpairs` keep their value. Missing options are
+/// assigned their default, provided that all of their dependencies are
+/// enabled either explicitly or by default. The result is in specification
+/// order.
+pub fn resolve<'a>(kvpairs: &[(String, String)], entries: &'a [ConfigEntry],
+                   graph: &Graph<&str, state::Complete>, log: &Logger)
+    -> Result<Vec<Resolved<'a>>, Box<dyn error::Error>>
+{
+    let mut resolved = Vec::with_capacity(entries.len());
     for ent in entries {
+        if let Some((_, v)) = kvpairs.iter().find(|(k, _)| *k == ent.name) {
+            log.writeln(Verbosity::Lvl2, &format!("Using configured value \"{}\" for option \"{}\"", v, ent.name));
+            resolved.push(Resolved { entry: ent, value: Some(v.clone()), origin: Origin::Explicit });
+            continue;
+        }
+
         let deps = graph.dependencies_of(&ent.name.as_ref())?;
         log.writeln(Verbosity::Lvl1, &format!("Checking dependencies of \"{}\"", ent.name));
 
-        enable = true;
+        let mut enable = true;
         for dep in deps {
             let dep = entries.iter()
                              .find(|e| e.name == dep)
@@ -22,9 +67,13 @@ pub fn defconfig(path: &path::PathBuf, entries: &[ConfigEntry], log: &Logger) ->
                 return Err(format!("Option {} depends on non-switch option {} which is not supported", ent.name, dep).into());
             }
 
-            // Safe to unwrap due to the above check
-            if !dep.is_enabled_by_default().unwrap() {
-                log.writeln(Verbosity::Lvl1, &format!("Skipping \"{}\" due to disabled dependency \"{}\"", ent, dep.name));
+            let enabled = match kvpairs.iter().find(|(k, _)| *k == dep.name) {
+                Some((_, v)) => v == "y",
+                // Safe to unwrap due to the above check
+                None => dep.is_enabled_by_default().unwrap()
+            };
+            if !enabled {
+                log.writeln(Verbosity::Lvl1, &format!("Skipping \"{}\" due to disabled dependency \"{}\"", ent.name, dep.name));
                 enable = false;
                 break;
             }
@@ -33,9 +82,485 @@ pub fn defconfig(path: &path::PathBuf, entries: &[ConfigEntry], log: &Logger) ->
         if enable {
             let default = ent.default_value();
             log.writeln(Verbosity::Lvl2, &format!("Choosing default \"{}\" for option \"{}\"", default, ent.name));
-            kvpairs.push((&ent.name, default));
+            resolved.push(Resolved { entry: ent, value: Some(default), origin: Origin::Default });
+        }
+        else {
+            resolved.push(Resolved { entry: ent, value: None, origin: Origin::Disabled });
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// Specification entries ordered such that every option comes after its dependencies
+pub fn dependency_order<'a>(entries: &'a [ConfigEntry], graph: &Graph<&str, state::Complete>)
+    -> Result<Vec<&'a ConfigEntry>, Box<dyn error::Error>>
+{
+    let by_name: collections::HashMap<&str, &ConfigEntry> = entries.iter()
+                                                                  .map(|e| (e.name.as_str(), e))
+                                                                  .collect();
+    let mut ordered = Vec::with_capacity(entries.len());
+    for opt in graph.topological_order() {
+        match by_name.get(opt) {
+            Some(ent) => ordered.push(*ent),
+            None => return Err(format!("Option {} is not in the specification", opt).into())
+        };
+    }
+    Ok(ordered)
+}
+
+/// Read the config at `path` and [`resolve`] it, treating a missing config as empty
+pub fn resolve_config<'a>(path: &path::PathBuf, entries: &'a [ConfigEntry],
+                          graph: &Graph<&str, state::Complete>, log: &Logger)
+    -> Result<Vec<Resolved<'a>>, Box<dyn error::Error>>
+{
+    let kvpairs = if path.exists() {
+        let lines: Vec<String> = fs::read_to_string(path)?
+                                    .split('\n')
+                                    .map(|s| s.to_owned())
+                                    .collect();
+        validate::validate_line_format(&lines, log)?;
+        parse::parse_config(path, Some(lines))?
+    }
+    else {
+        vec![]
+    };
+    resolve(&kvpairs, entries, graph, log)
+}
+
+/// Input available to a [`Generator`]
+#[derive(Debug)]
+pub struct Context<'a> {
+    /// Path of the current config, read on the first call to [`Context::resolved`]
+    config: Option<&'a path::PathBuf>,
+    /// Current config merged with the specification defaults, once resolved
+    resolved: cell::OnceCell<Vec<Resolved<'a>>>,
+    /// Config specification
+    pub entries: &'a [ConfigEntry],
+    /// Dependency graph of the specification
+    pub graph: &'a Graph<&'a str, state::Complete>,
+    /// Whether outputs are written or only checked
+    pub mode: WriteMode,
+    /// Generator specific `key=value` parameters
+    pub params: &'a [(String, String)],
+    /// Logger
+    pub log: &'a Logger,
+    /// Set once an output is found to be out of date
+    stale: cell::Cell<bool>
+}
+
+impl<'a> Context<'a> {
+    /// Create a context for the config at `config`, which is only read if a
+    /// generator asks for it
+    pub fn new(config: &'a path::PathBuf, entries: &'a [ConfigEntry], graph: &'a Graph<&'a str, state::Complete>,
+               mode: WriteMode, params: &'a [(String, String)], log: &'a Logger) -> Self {
+        Context {
+            config: Some(config),
+            resolved: cell::OnceCell::new(),
+            entries,
+            graph,
+            mode,
+            params,
+            log,
+            stale: cell::Cell::new(false)
+        }
+    }
+
+    /// Create a context for an already resolved config
+    pub fn with_resolved(resolved: Vec<Resolved<'a>>, entries: &'a [ConfigEntry],
+                         graph: &'a Graph<&'a str, state::Complete>, mode: WriteMode,
+                         params: &'a [(String, String)], log: &'a Logger) -> Self {
+        Context {
+            config: None,
+            resolved: cell::OnceCell::from(resolved),
+            entries,
+            graph,
+            mode,
+            params,
+            log,
+            stale: cell::Cell::new(false)
+        }
+    }
+
+    /// Current config merged with the specification defaults, see [`resolve_config`]
+    pub fn resolved(&self) -> Result<&[Resolved<'a>], Box<dyn error::Error>> {
+        if let Some(resolved) = self.resolved.get() {
+            return Ok(resolved);
+        }
+        let resolved = match self.config {
+            Some(config) => resolve_config(config, self.entries, self.graph, self.log)?,
+            None => resolve(&[], self.entries, self.graph, self.log)?
+        };
+        Ok(self.resolved.get_or_init(|| resolved))
+    }
+
+    /// Look up a generator parameter, the last occurrence taking precedence
+    pub fn param(&self, key: &str) -> Option<&'a str> {
+        self.params.iter()
+                   .rev()
+                   .find(|(k, _)| k == key)
+                   .map(|(_, v)| v.as_str())
+    }
+
+    /// Write generated contents to `path`, leaving it untouched if already up to date
+    pub fn write(&self, path: &path::Path, contents: &str) -> Result<Outcome, Box<dyn error::Error>> {
+        let outcome = output::write(path, contents, self.mode, self.log)?;
+        if outcome == Outcome::Stale {
+            self.stale.set(true);
+        }
+        Ok(outcome)
+    }
+
+    /// Remove an output that is no longer generated, see [`output::remove`]
+    pub fn remove(&self, path: &path::Path) -> Result<Outcome, Box<dyn error::Error>> {
+        let outcome = output::remove(path, self.mode, self.log)?;
+        if outcome == Outcome::Stale {
+            self.stale.set(true);
+        }
+        Ok(outcome)
+    }
+
+    /// Whether any output written through this context was out of date
+    pub fn is_stale(&self) -> bool {
+        self.stale.get()
+    }
+}
+
+/// Output format that can be produced from a config and its specification
+pub trait Generator: fmt::Debug {
+    /// Name by which the generator is selected
+    fn name(&self) -> &str;
+
+    /// One-line description shown when listing generators
+    fn description(&self) -> &str;
+
+    /// Whether the config file itself is written when no output is given
+    fn targets_config(&self) -> bool {
+        false
+    }
+
+    /// Whether the generator reads [`Invocation::params`], runs with
+    /// parameters are refused otherwise
+    fn takes_params(&self) -> bool {
+        false
+    }
+
+    /// Generate output and write it to `output` using [`Context::write`]
+    fn generate(&self, ctx: &Context<'_>, output: &path::Path) -> Result<(), Box<dyn error::Error>>;
+}
+
+/// Collection of generators, looked up by name
+#[derive(Debug)]
+pub struct Registry {
+    generators: Vec<Box<dyn Generator>>
+}
+
+impl Registry {
+    /// Create a registry without any generators
+    pub fn new() -> Self {
+        Registry { generators: vec![] }
+    }
+
+    /// Create a registry containing all generators shipped with conftool
+    pub fn with_builtins() -> Self {
+        let mut registry = Registry::new();
+        let builtins: Vec<Box<dyn Generator>> = vec![
+            Box::new(Defconfig),
+            Box::new(Savedefconfig),
+            Box::new(bulk::Allyesconfig),
+            Box::new(bulk::Allnoconfig),
+            Box::new(bulk::Randconfig),
+            Box::new(bindings::Python),
+            Box::new(bindings::Go),
+            Box::new(bindings::Javascript),
+            Box::new(split::Split)
+        ];
+        for gen in builtins {
+            // Builtin names are unique
+            registry.register(gen).unwrap();
+        }
+        registry
+    }
+
+    /// Add a generator, failing if its name is already taken
+    pub fn register(&mut self, generator: Box<dyn Generator>) -> Result<(), Box<dyn error::Error>> {
+        if self.find(generator.name()).is_some() {
+            return Err(format!("Generator \"{}\" already registered", generator.name()).into());
+        }
+        self.generators.push(generator);
+        Ok(())
+    }
+
+    /// Look up a generator by name
+    pub fn find(&self, name: &str) -> Option<&dyn Generator> {
+        self.generators.iter()
+                       .find(|g| g.name() == name)
+                       .map(|g| g.as_ref())
+    }
+
+    /// Iterate over all generators in registration order
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Generator> {
+        self.generators.iter().map(|g| g.as_ref())
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Registry::with_builtins()
+    }
+}
+
+/// Settings for a single generator run
+#[derive(Debug, Default)]
+pub struct Invocation {
+    /// Output path, the config is used if `None` and the generator targets it
+    pub output: Option<path::PathBuf>,
+    /// Whether outputs are written or only checked
+    pub mode: WriteMode,
+    /// Generator specific `key=value` parameters
+    pub params: Vec<(String, String)>
+}
+
+/// Run the generator called `name`
+///
+/// The config at `config` is merged with the specification defaults when the
+/// generator asks for it. A missing config is treated as empty. If no output is
+/// given, generators targeting the config write to `config`, all others fail.
+/// In [`WriteMode::Check`], an error is returned if any output is out of date.
+pub fn run(registry: &Registry, name: &str, config: &path::PathBuf, invocation: &Invocation,
+           entries: &[ConfigEntry], graph: &Graph<&str, state::Complete>, log: &Logger)
+    -> Result<(), Box<dyn error::Error>>
+{
+    let generator = match registry.find(name) {
+        Some(generator) => generator,
+        None => return Err(format!("Unknown generator \"{}\"", name).into())
+    };
+    let output = match &invocation.output {
+        Some(output) => output,
+        None if generator.targets_config() => config,
+        None => return Err(format!("Output path required when generating {}", name).into())
+    };
+    if !invocation.params.is_empty() && !generator.takes_params() {
+        return Err(format!("Generator \"{}\" takes no parameters, --seed and --probability only apply to randconfig",
+                           name).into());
+    }
+
+    let ctx = Context::new(config, entries, graph, invocation.mode, &invocation.params, log);
+    log.writeln(Verbosity::Lvl1, &format!("Running generator \"{}\"", name));
+    generator.generate(&ctx, output)?;
+    if ctx.is_stale() {
+        return Err(format!("Output of generator \"{}\" is out of date", name).into());
+    }
+    Ok(())
+}
+
+/// Print name and description of all generators in the registry
+pub fn list(registry: &Registry) {
+    let pad = registry.iter()
+                      .map(|g| g.name().len())
+                      .max()
+                      .unwrap_or(0);
+    for gen in registry.iter() {
+        println!("{:width$}  {}", gen.name(), gen.description(), width=pad);
+    }
+}
+
+/// Config with all options set to their defaults
+#[derive(Debug)]
+pub struct Defconfig;
+
+impl Generator for Defconfig {
+    fn name(&self) -> &str {
+        "defconfig"
+    }
+
+    fn description(&self) -> &str {
+        "Config with every option set to its default"
+    }
+
+    fn targets_config(&self) -> bool {
+        true
+    }
+
+    fn generate(&self, ctx: &Context<'_>, output: &path::Path) -> Result<(), Box<dyn error::Error>> {
+        let kvpairs = defaults(ctx.entries, ctx.graph, ctx.log)?;
+        ctx.write(output, &manipulate::format_config(&kvpairs, ctx.log))?;
+        Ok(())
+    }
+}
+
+/// Minimal config containing only values that differ from the defaults
+#[derive(Debug)]
+pub struct Savedefconfig;
+
+impl Generator for Savedefconfig {
+    fn name(&self) -> &str {
+        "savedefconfig"
+    }
+
+    fn description(&self) -> &str {
+        "Minimal config with only non-default values"
+    }
+
+    fn generate(&self, ctx: &Context<'_>, output: &path::Path) -> Result<(), Box<dyn error::Error>> {
+        let kvpairs = minimize(ctx.resolved()?, ctx.entries, ctx.graph, ctx.log)?;
+        ctx.write(output, &manipulate::format_config(&kvpairs, ctx.log))?;
+        Ok(())
+    }
+}
+
+/// Find the smallest config that [`resolve`]s to the same values as `target` and passes validation
+///
+/// Options are visited once in dependency order, keeping every option whose value
+/// differs from what the options kept so far resolve it to. Validation requires the
+/// dependencies of configured options to be listed, so those are kept as well. Options
+/// without a value in `target` cannot be expressed in a config and are left to their
+/// dependencies.
+pub fn minimize(target: &[Resolved<'_>], entries: &[ConfigEntry], graph: &Graph<&str, state::Complete>, log: &Logger)
+    -> Result<Vec<(String, String)>, Box<dyn error::Error>>
+{
+    let targets: collections::HashMap<&str, &Resolved<'_>> = target.iter()
+                                                                  .map(|r| (r.entry.name.as_str(), r))
+                                                                  .collect();
+
+    let mut kept: collections::HashMap<&str, String> = collections::HashMap::new();
+    for opt in graph.topological_order() {
+        let tgt = match targets.get(opt) {
+            Some(tgt) => tgt,
+            None => return Err(format!("Option {} is not in the specification", opt).into())
+        };
+        let value = match &tgt.value {
+            Some(value) => value,
+            None => continue
+        };
+
+        // Mirrors resolve, which looks at the configured or default value of every dependency
+        let deps = graph.dependencies_of(&opt)?;
+        let enabled = deps.iter().all(|dep| match kept.get(dep) {
+            Some(v) => v == "y",
+            None => targets.get(dep).is_some_and(|r| r.entry.is_enabled_by_default().unwrap_or(false))
+        });
+        let implied = match (enabled, tgt.entry.is_switch()) {
+            (true, _) => Some(tgt.entry.default_value()),
+            (false, true) => Some("n".to_string()),
+            (false, false) => None
+        };
+        if implied.as_deref() != tgt.effective_value() {
+            log.writeln(Verbosity::Lvl2, &format!("Keeping non-default value \"{}\" for option \"{}\"", value, opt));
+            kept.insert(opt, value.clone());
         }
     }
 
+    let configured: Vec<&str> = kept.iter()
+                                    .filter(|(opt, v)| !targets[*opt].entry.is_switch() || *v == "y")
+                                    .map(|(opt, _)| *opt)
+                                    .collect();
+    for opt in configured {
+        for dep in graph.dependencies_of(&opt)? {
+            if !kept.contains_key(dep) {
+                log.writeln(Verbosity::Lvl2, &format!("Listing dependency \"{}\" of option \"{}\"", dep, opt));
+                kept.insert(dep, "y".to_string());
+            }
+        }
+    }
+
+    // Emit in specification order
+    Ok(entries.iter()
+              .filter_map(|e| kept.remove(e.name.as_str()).map(|v| (e.name.clone(), v)))
+              .collect())
+}
+
+fn defaults<'a>(entries: &'a [ConfigEntry], graph: &Graph<&str, state::Complete>, log: &Logger)
+    -> Result<Vec<(&'a str, String)>, Box<dyn error::Error>>
+{
+    Ok(resolve(&[], entries, graph, log)?
+        .into_iter()
+        .filter_map(|r| r.value.map(|v| (r.entry.name.as_str(), v)))
+        .collect())
+}
+
+pub fn defconfig(path: &path::Path, entries: &[ConfigEntry], graph: &Graph<&str, state::Complete>, log: &Logger)
+    -> Result<(), Box<dyn error::Error>>
+{
+    let kvpairs = defaults(entries, graph, log)?;
     manipulate::write_config(&kvpairs, path, log)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::generate::*;
+    use crate::test_util::{entry, kvpairs};
+    use crate::{EntryType, Switch};
+
+    fn entries() -> Vec<ConfigEntry> {
+        vec![
+            entry("CONFIG_NET_PORT", &["CONFIG_NET"], EntryType::Int(80)),
+            entry("CONFIG_NET", &["CONFIG_BASE"], EntryType::Switch(Switch::Yes)),
+            entry("CONFIG_BASE", &[], EntryType::Switch(Switch::Yes)),
+            entry("CONFIG_DEBUG", &[], EntryType::Switch(Switch::No))
+        ]
+    }
+
+    #[test]
+    fn minimal_config_of_defaults_is_empty() -> Result<(), Box<dyn error::Error>> {
+        let entries = entries();
+        let graph = parse::spec_graph(&entries)?;
+        let log = Logger::new(0);
+        let target = resolve(&[], &entries, &graph, &log)?;
+        assert!(minimize(&target, &entries, &graph, &log)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn values_implied_by_dependencies_are_omitted() -> Result<(), Box<dyn error::Error>> {
+        let entries = entries();
+        let graph = parse::spec_graph(&entries)?;
+        let log = Logger::new(0);
+        let config = kvpairs(&[("CONFIG_BASE", "n"), ("CONFIG_NET", "n"), ("CONFIG_DEBUG", "y")]);
+        let target = resolve(&config, &entries, &graph, &log)?;
+        let minimal = minimize(&target, &entries, &graph, &log)?;
+        assert_eq!(minimal, kvpairs(&[("CONFIG_BASE", "n"), ("CONFIG_DEBUG", "y")]));
+        Ok(())
+    }
+
+    #[test]
+    fn minimal_config_resolves_to_original() -> Result<(), Box<dyn error::Error>> {
+        let entries = entries();
+        let graph = parse::spec_graph(&entries)?;
+        let log = Logger::new(0);
+        let config = kvpairs(&[("CONFIG_NET_PORT", "8080"), ("CONFIG_NET", "y"), ("CONFIG_BASE", "y")]);
+        let target = resolve(&config, &entries, &graph, &log)?;
+        let minimal = minimize(&target, &entries, &graph, &log)?;
+        assert_eq!(minimal, kvpairs(&[("CONFIG_NET_PORT", "8080"), ("CONFIG_NET", "y"), ("CONFIG_BASE", "y")]));
+        validate::validate_kvpairs(&minimal, &entries, &graph, &log)?;
+        let roundtrip = resolve(&minimal, &entries, &graph, &log)?;
+        for (a, b) in roundtrip.iter().zip(&target) {
+            assert_eq!(a.effective_value(), b.effective_value());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn config_is_only_read_by_generators_using_it() -> Result<(), Box<dyn error::Error>> {
+        let entries = entries();
+        let graph = parse::spec_graph(&entries)?;
+        let log = Logger::new(0);
+        let dir = std::env::temp_dir();
+        let config = dir.join(format!("conftool-run-{}-config", std::process::id()));
+        let output = dir.join(format!("conftool-run-{}-output", std::process::id()));
+        fs::write(&config, "not a config line\n")?;
+        let invocation = |output: &path::Path| Invocation { output: Some(output.to_path_buf()), ..Invocation::default() };
+
+        let registry = Registry::default();
+        run(&registry, "defconfig", &config, &invocation(&output), &entries, &graph, &log)?;
+        assert_eq!(fs::read_to_string(&output)?,
+                   "CONFIG_NET_PORT = 80\nCONFIG_NET      = y\nCONFIG_BASE     = y\nCONFIG_DEBUG    = n\n");
+        assert!(run(&registry, "savedefconfig", &config, &invocation(&output), &entries, &graph, &log).is_err());
+        let seeded = Invocation { params: vec![("seed".to_string(), "1".to_string())], ..invocation(&output) };
+        assert!(run(&registry, "defconfig", &config, &seeded, &entries, &graph, &log).is_err());
+
+        fs::remove_file(&config)?;
+        fs::remove_file(&output)?;
+        Ok(())
+    }
+}
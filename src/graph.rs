@@ -1,27 +1,48 @@
 use core::fmt;
-use std::{convert, error, marker};
-use crate::ConfigEntry;
-
-#[derive(Debug)]
-enum Parent<T> {
-    Vertex(usize),
-    Outstanding(T)
+use std::{cell, cmp, collections, error, hash, marker, rc};
+
+/// Relationship an edge expresses between a vertex and the one it points to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EdgeKind {
+    /// The vertex is only available when the target is enabled
+    Depends,
+    /// Enabling the vertex suggests enabling the target, without requiring it
+    Implies,
+    /// Enabling the vertex forces the target on
+    Selects,
+    /// The vertex and the target cannot be enabled together
+    Conflicts,
+    /// The vertex is shown inside the target menu
+    Contains
 }
 
+/// Edge kinds making a vertex require its target, the only ones followed by
+/// traversals that do not take edge kinds
+const REQUIRING: [EdgeKind; 2] = [EdgeKind::Depends, EdgeKind::Selects];
+
 #[derive(Debug)]
 struct Vertex<T> {
     /// Value of the node
     value: T,
-    /// Links to vertices who have at least
-    /// one inbound child edge
-    parents: Vec<Parent<T>>,
-    /// Links to child vertices
+    /// Vertices this one depends on, in the order given on insertion
+    parents: Vec<usize>,
+    /// Kind of the edge to each parent
+    kinds: Vec<EdgeKind>,
+    /// Vertices depending on this one, in insertion order
     children: Vec<usize>
 }
 
 impl<T> Vertex<T> {
-    fn new(value: T, parents: Vec<Parent<T>>, children: Vec<usize>) -> Self {
-        Vertex { value, parents, children }
+    fn new(value: T) -> Self {
+        Vertex { value, parents: vec![], kinds: vec![], children: vec![] }
+    }
+
+    /// Parents over edges of a [`REQUIRING`] kind
+    fn required(&self) -> impl Iterator<Item = usize> + '_ {
+        self.parents.iter()
+                    .zip(&self.kinds)
+                    .filter(|(_, kind)| REQUIRING.contains(kind))
+                    .map(|(&p, _)| p)
     }
 }
 
@@ -30,7 +51,7 @@ where
     T: PartialEq
 {
     fn eq(&self, other: &Self) -> bool {
-        return self.value == other.value;
+        self.value == other.value
     }
 }
 
@@ -39,10 +60,22 @@ where
     T: Eq
 { }
 
+/// Transitive closures computed so far, by vertex index
+#[derive(Debug, Default)]
+struct Closures {
+    dependencies: cell::RefCell<collections::HashMap<usize, rc::Rc<[usize]>>>,
+    dependents: cell::RefCell<collections::HashMap<usize, rc::Rc<[usize]>>>
+}
+
 #[derive(Debug)]
 pub struct Graph<T, COMPLETE> {
     /// Graph vertices
     vertices: Vec<Vertex<T>>,
+    /// Index of every vertex by value
+    index: collections::HashMap<T, usize>,
+    /// Dependencies not inserted yet, with the vertices and parent slots waiting for them
+    outstanding: collections::HashMap<T, Vec<(usize, usize)>>,
+    closures: Closures,
     phantom: marker::PhantomData<COMPLETE>
 }
 
@@ -53,69 +86,35 @@ pub mod state {
     pub struct Complete;
 }
 
-impl<T> Graph<T, state::Incomplete>
+impl<T> Default for Graph<T, state::Incomplete>
 where
-    T: fmt::Debug + Clone + PartialEq
+    T: fmt::Debug + Clone + Eq + hash::Hash
 {
-    pub fn new() -> Self {
-        Graph { vertices: Vec::with_capacity(32), phantom: marker::PhantomData }
-    }
-
-    fn is_unique(&self, value: &T) -> bool {
-        self.vertices.iter().find(|&x| *value == x.value).is_none()
-    }
-
-    fn find_parent_vertices(&self, depends: &[T]) -> Vec<Parent<T>> {
-        let mut parents: Vec<Parent<T>> = vec![];
-        let mut found: bool;
-        for dep in depends {
-            found = false;
-            for (i, vert) in self.vertices.iter().enumerate() {
-                if vert.value == *dep {
-                    parents.push(Parent::Vertex(i));
-                    found = true;
-                    break;
-                }
-            }
-            if !found {
-                parents.push(Parent::Outstanding(dep.clone()));
-            }
-        }
-        return parents;
+    fn default() -> Self {
+        Self::new()
     }
+}
 
-    fn link_children(&mut self, parents: &[Parent<T>]) {
-        let idx = self.vertices.len();
-        for p in parents {
-            if let Parent::Vertex(i) = p {
-                self.vertices[*i].children.push(idx);
-            }
-        }
-    }
+/// Placeholder parent index of a dependency that was not inserted yet
+const OUTSTANDING: usize = usize::MAX;
 
-    fn find_child_vertices(&self, value: &T) -> Vec<(usize, usize)> {
-        let mut children: Vec<(usize, usize)> = vec![];
-        for (i, vert) in self.vertices.iter().enumerate() {
-            for (j, p) in vert.parents.iter().enumerate() {
-                if let Parent::Outstanding(name) = p {
-                    if name == value {
-                        children.push((i, j));
-                    }
-                }
-            }
-        }
-        return children;
-    }
-
-    fn link_parents(&mut self, children: &Vec<(usize, usize)>) {
-        let idx = self.vertices.len();
-        for (i, j) in children {
-            self.vertices[*i].parents[*j] = Parent::Vertex(idx);
+impl<T> Graph<T, state::Incomplete>
+where
+    T: fmt::Debug + Clone + Eq + hash::Hash
+{
+    pub fn new() -> Self {
+        Graph {
+            vertices: Vec::with_capacity(32),
+            index: collections::HashMap::with_capacity(32),
+            outstanding: collections::HashMap::new(),
+            closures: Closures::default(),
+            phantom: marker::PhantomData
         }
     }
 
     fn contains_duplicates(slice: &[T]) -> bool {
-        (1..slice.len()).any(|i| slice[i..].contains(&slice[i - 1]))
+        let mut seen = collections::HashSet::with_capacity(slice.len());
+        !slice.iter().all(|x| seen.insert(x))
     }
 
     /// Insert a vertex in the graph, supplying its dependencies
@@ -129,129 +128,503 @@ where
     /// * `value`- The value of the node to insert
     ///
     /// * `depends` - Values on which the node to be inserted depends. Must not
-    ///               contain duplicates or `value`.
+    ///   contain duplicates or `value`.
     ///
     pub fn insert(&mut self, value: T, depends: &[T]) -> Result<(), Box<dyn error::Error>> {
+        self.insert_edges(value, &Graph::<T, state::Incomplete>::depends_edges(depends))
+    }
+
+    /// Insert a vertex in the graph, supplying its edges and their kinds
+    ///
+    /// Behaves like [`Self::insert`], a vertex can have at most one edge to
+    /// each other vertex.
+    pub fn insert_edges(&mut self, value: T, edges: &[(T, EdgeKind)]) -> Result<(), Box<dyn error::Error>> {
+        if self.index.contains_key(&value) {
+            return Err(format!("Vertex {:?} already in graph", value).into());
+        }
+        let depends: Vec<T> = edges.iter().map(|(d, _)| d.clone()).collect();
+        Graph::<T, state::Incomplete>::check_depends(&value, &depends)?;
+
+        let idx = self.vertices.len();
+        self.vertices.push(Vertex::new(value.clone()));
+        self.link(idx, edges);
+        self.index.insert(value.clone(), idx);
+        self.link_waiting(&value, idx);
+
+        Ok(())
+    }
+
+    fn depends_edges(depends: &[T]) -> Vec<(T, EdgeKind)> {
+        depends.iter().map(|d| (d.clone(), EdgeKind::Depends)).collect()
+    }
+
+    fn check_depends(value: &T, depends: &[T]) -> Result<(), Box<dyn error::Error>> {
         if Graph::<T, state::Incomplete>::contains_duplicates(depends) {
             return Err(format!("Dependencies {:?} contains duplicates", depends).into());
         }
-        if !self.is_unique(&value) {
-            return Err(format!("Vertex {:?} already in graph", value).into());
-        }
-        if depends.contains(&value) {
+        if depends.contains(value) {
             return Err(format!("Vertex {:?} cannot depend on itself", value).into());
         }
+        Ok(())
+    }
+
+    fn find_vertex(&self, value: &T) -> Result<usize, Box<dyn error::Error>> {
+        match self.index.get(value) {
+            Some(&vert) => Ok(vert),
+            None => Err(format!("No vertex matches {:?}", value).into())
+        }
+    }
+
+    /// Add `edges` from vertex `idx`, leaving the ones to missing vertices outstanding
+    fn link(&mut self, idx: usize, edges: &[(T, EdgeKind)]) {
+        let mut parents = Vec::with_capacity(edges.len());
+        for (slot, (dep, _)) in edges.iter().enumerate() {
+            match self.index.get(dep) {
+                Some(&p) => {
+                    self.vertices[p].children.push(idx);
+                    parents.push(p);
+                },
+                None => {
+                    self.outstanding.entry(dep.clone()).or_default().push((idx, slot));
+                    parents.push(OUTSTANDING);
+                }
+            };
+        }
+        self.vertices[idx].parents = parents;
+        self.vertices[idx].kinds = edges.iter().map(|(_, kind)| *kind).collect();
+    }
+
+    /// Connect vertices waiting for `value` to vertex `idx`
+    fn link_waiting(&mut self, value: &T, idx: usize) {
+        if let Some(waiting) = self.outstanding.remove(value) {
+            for (child, slot) in waiting {
+                self.vertices[child].parents[slot] = idx;
+                self.vertices[idx].children.push(child);
+            }
+        }
+    }
+
+    /// Remove all edges from vertex `idx` to its dependencies
+    fn unlink(&mut self, idx: usize) {
+        let parents = std::mem::take(&mut self.vertices[idx].parents);
+        self.vertices[idx].kinds.clear();
+        for &p in &parents {
+            if p != OUTSTANDING {
+                self.vertices[p].children.retain(|&c| c != idx);
+            }
+        }
+        if parents.contains(&OUTSTANDING) {
+            self.outstanding.retain(|_, waiting| {
+                waiting.retain(|(v, _)| *v != idx);
+                !waiting.is_empty()
+            });
+        }
+    }
+
+    /// Remove a vertex from the graph
+    ///
+    /// Edges of vertices depending on the removed one are kept and become
+    /// dangling, see [`Self::dangling_edges`].
+    pub fn remove(&mut self, value: &T) -> Result<(), Box<dyn error::Error>> {
+        let idx = self.find_vertex(value)?;
+        self.unlink(idx);
+        for child in std::mem::take(&mut self.vertices[idx].children) {
+            for (slot, p) in self.vertices[child].parents.iter_mut().enumerate() {
+                if *p == idx {
+                    *p = OUTSTANDING;
+                    self.outstanding.entry(value.clone()).or_default().push((child, slot));
+                }
+            }
+        }
+        self.vertices.remove(idx);
+        self.index.remove(value);
+
+        // Indices after the removed vertex shift down by one
+        let shift = |i: &mut usize| if *i != OUTSTANDING && *i > idx { *i -= 1 };
+        for vert in &mut self.vertices {
+            vert.parents.iter_mut().for_each(shift);
+            vert.children.iter_mut().for_each(shift);
+        }
+        self.index.values_mut().for_each(shift);
+        for waiting in self.outstanding.values_mut() {
+            waiting.iter_mut().for_each(|(v, _)| shift(v));
+        }
+        Ok(())
+    }
 
-        let parents = self.find_parent_vertices(depends);
-        self.link_children(&parents);
-        let children = self.find_child_vertices(&value);
-        self.link_parents(&children);
-        let children = children.iter().map(|(i, _)| *i).collect();
-        self.vertices.push(Vertex::new(value, parents, children));
+    /// Rename a vertex, keeping the edges of vertices depending on it
+    ///
+    /// Dangling edges to `new` are connected to the renamed vertex.
+    pub fn rename(&mut self, old: &T, new: T) -> Result<(), Box<dyn error::Error>> {
+        let idx = self.find_vertex(old)?;
+        if self.index.contains_key(&new) {
+            return Err(format!("Vertex {:?} already in graph", new).into());
+        }
+        if let Some(waiting) = self.outstanding.get(&new) {
+            for (child, _) in waiting {
+                if *child == idx {
+                    return Err(format!("Vertex {:?} cannot depend on itself", new).into());
+                }
+                if self.vertices[*child].parents.contains(&idx) {
+                    return Err(format!("Vertex {:?} would depend on {:?} twice", self.vertices[*child].value, new).into());
+                }
+            }
+        }
 
+        self.index.remove(old);
+        self.index.insert(new.clone(), idx);
+        self.vertices[idx].value = new.clone();
+        self.link_waiting(&new, idx);
         Ok(())
     }
 
+    /// Replace the dependencies of a vertex
+    pub fn relink(&mut self, value: &T, depends: &[T]) -> Result<(), Box<dyn error::Error>> {
+        self.relink_edges(value, &Graph::<T, state::Incomplete>::depends_edges(depends))
+    }
+
+    /// Replace the edges of a vertex, see [`Self::insert_edges`]
+    pub fn relink_edges(&mut self, value: &T, edges: &[(T, EdgeKind)]) -> Result<(), Box<dyn error::Error>> {
+        let idx = self.find_vertex(value)?;
+        let depends: Vec<T> = edges.iter().map(|(d, _)| d.clone()).collect();
+        Graph::<T, state::Incomplete>::check_depends(value, &depends)?;
+        self.unlink(idx);
+        self.link(idx, edges);
+        Ok(())
+    }
+
+    /// Return the edges to dependencies that are not in the graph, as pairs of
+    /// the depending value and the missing dependency, in insertion order
+    pub fn dangling_edges(&self) -> Vec<(T, T)> {
+        let mut edges: Vec<(usize, usize, &T)> = self.outstanding.iter()
+                                                     .flat_map(|(dep, waiting)| waiting.iter().map(move |&(v, slot)| (v, slot, dep)))
+                                                     .collect();
+        edges.sort_by_key(|&(v, slot, _)| (v, slot));
+        edges.into_iter()
+             .map(|(v, _, dep)| (self.vertices[v].value.clone(), dep.clone()))
+             .collect()
+    }
+
     fn is_complete(&self) -> bool {
-        self.vertices
-            .iter()
-            .find(|&v| !v.parents
-                        .iter()
-                        .find(|&p| match p {
-                            Parent::Outstanding(_) => true,
-                            _ => false
-                        })
-                        .is_none())
-            .is_none()
-    }
-
-    pub fn into_complete(self) -> Result<Graph<T, state::Complete>, Box<dyn error::Error>> {
+        self.outstanding.is_empty()
+    }
+
+    /// Find a cycle of dependencies, returned as a path starting and ending in the same vertex
+    ///
+    /// Only edges of a [`REQUIRING`] kind count, options may conflict with each other.
+    fn find_cycle(&self) -> Option<Vec<usize>> {
+        #[derive(Clone, Copy, PartialEq)]
+        enum Mark {
+            Unvisited,
+            OnPath,
+            Done
+        }
+
+        let mut marks = vec![Mark::Unvisited; self.vertices.len()];
+        for start in 0..self.vertices.len() {
+            if marks[start] != Mark::Unvisited {
+                continue;
+            }
+            // Current path, each vertex with the index of the next parent to visit
+            let mut path: Vec<(usize, usize)> = vec![(start, 0)];
+            marks[start] = Mark::OnPath;
+            while let Some((vert, next)) = path.last_mut() {
+                let vert = *vert;
+                let parents: Vec<usize> = self.vertices[vert].required().collect();
+                if *next == parents.len() {
+                    marks[vert] = Mark::Done;
+                    path.pop();
+                    continue;
+                }
+                let parent = parents[*next];
+                *next += 1;
+                match marks[parent] {
+                    Mark::Unvisited => {
+                        marks[parent] = Mark::OnPath;
+                        path.push((parent, 0));
+                    },
+                    Mark::OnPath => {
+                        let pos = path.iter().position(|(v, _)| *v == parent).unwrap();
+                        let mut cycle: Vec<usize> = path[pos..].iter().map(|(v, _)| *v).collect();
+                        cycle.push(parent);
+                        return Some(cycle);
+                    },
+                    Mark::Done => ()
+                };
+            }
+        }
+        None
+    }
+
+    /// Check that all dependencies were inserted and that no option depends on itself
+    /// through other options
+    pub fn into_complete(self) -> Result<Graph<T, state::Complete>, Box<dyn error::Error>>
+    where
+        T: fmt::Display
+    {
         if !self.is_complete() {
-            return Err("Cannot complete graph with non-connected edges".into())
+            let mut missing: Vec<String> = self.outstanding.keys().map(|k| k.to_string()).collect();
+            missing.sort();
+            return Err(format!("Cannot complete graph with non-connected edges, missing {}", missing.join(", ")).into())
+        }
+        if let Some(cycle) = self.find_cycle() {
+            let cycle: Vec<String> = cycle.iter().map(|&v| self.vertices[v].value.to_string()).collect();
+            return Err(format!("Dependency cycle: {}", cycle.join(" -> ")).into());
         }
 
-        Ok(Graph { vertices: self.vertices, phantom: marker::PhantomData })
+        Ok(Graph {
+            vertices: self.vertices,
+            index: self.index,
+            outstanding: self.outstanding,
+            closures: Closures::default(),
+            phantom: marker::PhantomData
+        })
     }
 }
 
 impl<T> Graph<T, state::Complete>
 where
-    T: fmt::Debug + Clone + PartialEq
+    T: fmt::Debug + Clone + Eq + hash::Hash
 {
     fn find_vertex(&self, value: &T) -> Result<usize, Box<dyn error::Error>> {
-        match self.vertices.iter().position(|v| v.value == *value) {
-            Some(vert) => Ok(vert),
+        match self.index.get(value) {
+            Some(&vert) => Ok(vert),
             None => Err(format!("No vertex matches {:?}", value).into())
         }
     }
 
-    /// Return a list of dependencies for the supplied value
+    /// Breadth-first closure of `start` over `edges`, cached in `cache`
+    ///
+    /// Only whole closures are cached, so the order is that of the traversal
+    /// regardless of which vertices were queried before.
+    fn closure<F>(&self, start: usize, edges: F, cache: &cell::RefCell<collections::HashMap<usize, rc::Rc<[usize]>>>)
+        -> rc::Rc<[usize]>
+    where
+        F: Fn(usize) -> Vec<usize>
+    {
+        if let Some(closure) = cache.borrow().get(&start) {
+            return closure.clone();
+        }
+
+        let mut traversed: collections::HashSet<usize> = collections::HashSet::new();
+        let mut closure: Vec<usize> = Vec::with_capacity(16);
+        let mut queue = collections::VecDeque::from([start]);
+        while let Some(vert) = queue.pop_front() {
+            for next in edges(vert) {
+                if traversed.insert(next) {
+                    closure.push(next);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        let closure: rc::Rc<[usize]> = closure.into();
+        cache.borrow_mut().insert(start, closure.clone());
+        closure
+    }
+
+    /// Kind of the edge from `child` to `parent`, which must exist
+    fn kind_between(&self, child: usize, parent: usize) -> EdgeKind {
+        let vert = &self.vertices[child];
+        // Safe to unwrap, children are only recorded along with the parent edge
+        vert.kinds[vert.parents.iter().position(|&p| p == parent).unwrap()]
+    }
+
+    /// Direct neighbours of `vert` over edges of the given kinds, towards its
+    /// dependencies if `upwards` or its dependents otherwise
+    fn neighbours(&self, vert: usize, kinds: &[EdgeKind], upwards: bool) -> Vec<usize> {
+        let v = &self.vertices[vert];
+        match upwards {
+            true => v.parents.iter()
+                             .zip(&v.kinds)
+                             .filter(|(_, kind)| kinds.contains(kind))
+                             .map(|(&p, _)| p)
+                             .collect(),
+            false => v.children.iter()
+                               .copied()
+                               .filter(|&c| kinds.contains(&self.kind_between(c, vert)))
+                               .collect()
+        }
+    }
+
+    /// Breadth-first closure of `start` over edges of the given kinds
+    fn closure_of_kinds(&self, start: usize, kinds: &[EdgeKind], upwards: bool) -> Vec<usize> {
+        let mut traversed: collections::HashSet<usize> = collections::HashSet::new();
+        let mut closure = vec![];
+        let mut queue = collections::VecDeque::from([start]);
+        while let Some(vert) = queue.pop_front() {
+            for next in self.neighbours(vert, kinds, upwards) {
+                if traversed.insert(next) {
+                    closure.push(next);
+                    queue.push_back(next);
+                }
+            }
+        }
+        closure
+    }
+
+    fn values_of(&self, indices: &[usize]) -> Vec<T> {
+        indices.iter().map(|&i| self.vertices[i].value.clone()).collect()
+    }
+
+    /// Return the graph in its editable state, see [`Graph::insert`], [`Graph::remove`],
+    /// [`Graph::rename`] and [`Graph::relink`]
+    pub fn into_incomplete(self) -> Graph<T, state::Incomplete> {
+        Graph {
+            vertices: self.vertices,
+            index: self.index,
+            outstanding: self.outstanding,
+            closures: Closures::default(),
+            phantom: marker::PhantomData
+        }
+    }
+
+    /// Return a list of dependencies for the supplied value, over edges of a
+    /// [`REQUIRING`] kind
     pub fn dependencies_of(&self, value: &T) -> Result<Vec<T>, Box<dyn error::Error>> {
         let vert = self.find_vertex(value)?;
+        let closure = self.closure(vert, |v| self.neighbours(v, &REQUIRING, true), &self.closures.dependencies);
+        Ok(self.values_of(&closure))
+    }
 
-        let mut to_traverse: Vec<_> = vec![vert];
-        let mut traversed: Vec<usize> = Vec::with_capacity(16);
-        let mut deps: Vec<T> = Vec::with_capacity(16);
-
-        while !to_traverse.is_empty() {
-            let vert = &self.vertices[to_traverse.pop().unwrap()];
-            let unique: Vec<usize> = vert.parents.iter()
-                                                 .map(|x| match x {
-                                                    Parent::Vertex(x) => *x,
-                                                    _ => panic!("Impossible")
-                                                  })
-                                                 .filter(|x| traversed.iter()
-                                                                      .find(|&y| x == y)
-                                                                      .is_none())
-                                                 .collect();
-            deps.extend_from_slice(unique.iter()
-                                         .map(|&i| self.vertices[i].value.clone())
-                                         .collect::<Vec<T>>()
-                                         .as_slice());
-            to_traverse.extend_from_slice(unique.as_slice());
-            traversed.extend_from_slice(unique.as_slice());
-        }
-        Ok(deps)
-    }
-
-    /// Return a list of vertices that depend on the given one
+    /// Return the dependencies the supplied value has an edge of a [`REQUIRING`] kind to
+    pub fn direct_dependencies_of(&self, value: &T) -> Result<Vec<T>, Box<dyn error::Error>> {
+        let vert = self.find_vertex(value)?;
+        Ok(self.values_of(&self.neighbours(vert, &REQUIRING, true)))
+    }
+
+    /// Return a list of vertices that depend on the given one, over edges of a
+    /// [`REQUIRING`] kind
     pub fn dependent_vertices(&self, value: &T) -> Result<Vec<T>, Box<dyn error::Error>> {
         let vert = self.find_vertex(value)?;
+        let closure = self.closure(vert, |v| self.neighbours(v, &REQUIRING, false), &self.closures.dependents);
+        Ok(self.values_of(&closure))
+    }
 
-        let mut to_traverse: Vec<_> = vec![vert];
-        let mut traversed: Vec<usize> = Vec::with_capacity(16);
-        let mut dependent: Vec<T> = Vec::with_capacity(16);
-
-        while !to_traverse.is_empty() {
-            let vert = &self.vertices[to_traverse.pop().unwrap()];
-            let unique: Vec<usize> = vert.children.iter()
-                                                  .filter(|&x| traversed.iter()
-                                                                       .find(|&y| x == y)
-                                                                       .is_none())
-                                                  .map(|x| *x)
-                                                  .collect();
-            dependent.extend_from_slice(unique.iter()
-                                              .map(|&i| self.vertices[i].value.clone())
-                                              .collect::<Vec<T>>()
-                                              .as_slice());
-            to_traverse.extend_from_slice(unique.as_slice());
-            traversed.extend_from_slice(unique.as_slice());
-        }
-        Ok(dependent)
+    /// Return the vertices with an edge of a [`REQUIRING`] kind to the given one
+    pub fn direct_dependents_of(&self, value: &T) -> Result<Vec<T>, Box<dyn error::Error>> {
+        let vert = self.find_vertex(value)?;
+        Ok(self.values_of(&self.neighbours(vert, &REQUIRING, false)))
     }
-}
 
-impl<'a> convert::From<&'a [ConfigEntry]> for Graph<&'a str, state::Incomplete> {
-    fn from(entries: &'a [ConfigEntry]) -> Self {
-        let mut graph: Graph<&str, state::Incomplete> = Graph::new();
-        for ent in entries {
-            graph.insert(&ent.name, &ent.depends
-                    .iter()
-                    .map(|s| s.as_str())
-                    .collect::<Vec<&str>>())
-                    .unwrap();
+    /// Return the kind of the edge from `from` to `to`, or `None` without such an edge
+    pub fn edge_kind(&self, from: &T, to: &T) -> Result<Option<EdgeKind>, Box<dyn error::Error>> {
+        let (from, to) = (self.find_vertex(from)?, self.find_vertex(to)?);
+        let vert = &self.vertices[from];
+        Ok(vert.parents.iter().position(|&p| p == to).map(|slot| vert.kinds[slot]))
+    }
+
+    /// Return a list of dependencies for the supplied value, following only
+    /// edges of the given kinds
+    pub fn dependencies_of_kind(&self, value: &T, kinds: &[EdgeKind]) -> Result<Vec<T>, Box<dyn error::Error>> {
+        let vert = self.find_vertex(value)?;
+        Ok(self.values_of(&self.closure_of_kinds(vert, kinds, true)))
+    }
+
+    /// Return the dependencies the supplied value has an edge of the given kinds to
+    pub fn direct_dependencies_of_kind(&self, value: &T, kinds: &[EdgeKind]) -> Result<Vec<T>, Box<dyn error::Error>> {
+        let vert = self.find_vertex(value)?;
+        Ok(self.values_of(&self.neighbours(vert, kinds, true)))
+    }
+
+    /// Return a list of vertices that depend on the given one, following only
+    /// edges of the given kinds
+    pub fn dependent_vertices_of_kind(&self, value: &T, kinds: &[EdgeKind]) -> Result<Vec<T>, Box<dyn error::Error>> {
+        let vert = self.find_vertex(value)?;
+        Ok(self.values_of(&self.closure_of_kinds(vert, kinds, false)))
+    }
+
+    /// Return the vertices with an edge of the given kinds to the given one
+    pub fn direct_dependents_of_kind(&self, value: &T, kinds: &[EdgeKind]) -> Result<Vec<T>, Box<dyn error::Error>> {
+        let vert = self.find_vertex(value)?;
+        Ok(self.values_of(&self.neighbours(vert, kinds, false)))
+    }
+
+    fn topological_indices(&self) -> Vec<usize> {
+        let mut pending: Vec<usize> = self.vertices.iter().map(|v| v.required().count()).collect();
+        let mut ready: collections::BinaryHeap<cmp::Reverse<usize>> = pending.iter()
+                                                                             .enumerate()
+                                                                             .filter(|(_, &n)| n == 0)
+                                                                             .map(|(i, _)| cmp::Reverse(i))
+                                                                             .collect();
+        let mut order = Vec::with_capacity(self.vertices.len());
+        while let Some(cmp::Reverse(vert)) = ready.pop() {
+            order.push(vert);
+            for child in self.neighbours(vert, &REQUIRING, false) {
+                pending[child] -= 1;
+                if pending[child] == 0 {
+                    ready.push(cmp::Reverse(child));
+                }
+            }
         }
-        graph
+        order
+    }
+
+    /// Return all values such that every one comes after its dependencies
+    ///
+    /// Among values whose dependencies are all placed, the one inserted first
+    /// comes first, so the order is stable for a given insertion order.
+    pub fn topological_order(&self) -> Vec<T> {
+        self.topological_indices()
+            .into_iter()
+            .map(|i| self.vertices[i].value.clone())
+            .collect()
+    }
+
+    /// Return the values without dependencies, in insertion order
+    pub fn roots(&self) -> Vec<T> {
+        self.vertices.iter()
+                     .filter(|v| v.required().next().is_none())
+                     .map(|v| v.value.clone())
+                     .collect()
+    }
+
+    /// Return the values nothing depends on, in insertion order
+    pub fn leaves(&self) -> Vec<T> {
+        (0..self.vertices.len()).filter(|&i| self.neighbours(i, &REQUIRING, false).is_empty())
+                                .map(|i| self.vertices[i].value.clone())
+                                .collect()
+    }
+
+    /// Return the length of the longest dependency path from the value to a root
+    pub fn depth_of(&self, value: &T) -> Result<usize, Box<dyn error::Error>> {
+        let vert = self.find_vertex(value)?;
+        let mut depths = vec![0usize; self.vertices.len()];
+        for i in self.topological_indices() {
+            depths[i] = self.vertices[i].required()
+                                        .map(|p| depths[p] + 1)
+                                        .max()
+                                        .unwrap_or(0);
+            if i == vert {
+                break;
+            }
+        }
+        Ok(depths[vert])
+    }
+
+    /// Return the shortest chain of dependencies leading from `from` to `to`,
+    /// including both, or `None` if `from` does not depend on `to`
+    pub fn shortest_path(&self, from: &T, to: &T) -> Result<Option<Vec<T>>, Box<dyn error::Error>> {
+        let (from, to) = (self.find_vertex(from)?, self.find_vertex(to)?);
+        let mut previous: collections::HashMap<usize, usize> = collections::HashMap::new();
+        let mut queue = collections::VecDeque::from([from]);
+        while let Some(vert) = queue.pop_front() {
+            if vert == to {
+                let mut path = vec![to];
+                while let Some(&p) = previous.get(path.last().unwrap()) {
+                    path.push(p);
+                }
+                path.reverse();
+                return Ok(Some(path.into_iter().map(|i| self.vertices[i].value.clone()).collect()));
+            }
+            for parent in self.vertices[vert].required() {
+                if parent != from && !previous.contains_key(&parent) {
+                    previous.insert(parent, vert);
+                    queue.push_back(parent);
+                }
+            }
+        }
+        Ok(None)
     }
 }
 
@@ -262,8 +635,8 @@ mod tests {
     #[test]
     fn single_dependency() -> Result<(), Box<dyn error::Error>> {
         let mut graph: Graph<&str, state::Incomplete> = Graph::new();
-        graph.insert("CONFIG_DEPENDENT", &vec!["CONFIG_PARENT"])?;
-        graph.insert("CONFIG_PARENT", &vec![])?;
+        graph.insert("CONFIG_DEPENDENT", &["CONFIG_PARENT"])?;
+        graph.insert("CONFIG_PARENT", &[])?;
         let graph = graph.into_complete()?;
         let deps = graph.dependencies_of(&"CONFIG_DEPENDENT")?;
 
@@ -276,7 +649,7 @@ mod tests {
     #[test]
     fn cannot_complete_graph_with_missing_dependencies() -> Result<(), Box<dyn error::Error>> {
         let mut graph: Graph<&str, state::Incomplete> = Graph::new();
-        graph.insert("CONFIG_TEST", &vec!["CONFIG_NEVER_INSERTED"])?;
+        graph.insert("CONFIG_TEST", &["CONFIG_NEVER_INSERTED"])?;
         assert!(graph.into_complete().is_err());
         Ok(())
     }
@@ -304,12 +677,12 @@ mod tests {
 
         if let Some((last, rest)) = opts.split_last() {
             for (i, opt) in rest.iter().enumerate() {
-                graph.insert(&opt, &[&opts[i + 1]])?;
+                graph.insert(opt, &[&opts[i + 1]])?;
             }
             graph.insert(last, &[])?;
         }
         else {
-            assert!(false);
+            unreachable!("options were just generated");
         }
 
         let graph = graph.into_complete()?;
@@ -326,10 +699,10 @@ mod tests {
     fn disjointed_graph() -> Result<(), Box<dyn error::Error>> {
         let mut graph: Graph<&str, state::Incomplete> = Graph::new();
         let opts = ["CONFIG0", "CONFIG1", "CONFIG2", "CONFIG3"];
-        graph.insert(&opts[0],&[&opts[1]])?;
-        graph.insert(&opts[1], &[])?;
-        graph.insert(&opts[2], &[&opts[3]])?;
-        graph.insert(&opts[3], &[])?;
+        graph.insert(opts[0], &[opts[1]])?;
+        graph.insert(opts[1], &[])?;
+        graph.insert(opts[2], &[opts[3]])?;
+        graph.insert(opts[3], &[])?;
         let graph = graph.into_complete()?;
         let deps = graph.dependencies_of(&opts[0])?;
         assert_eq!(deps, &[opts[1]]);
@@ -345,20 +718,20 @@ mod tests {
     #[test]
     fn vertex_cannot_depend_on_itself() {
         let mut graph: Graph<&str, state::Incomplete> = Graph::new();
-        assert!(graph.insert(&"CONFIG_TEST", &[&"CONFIG_TEST"]).is_err())
+        assert!(graph.insert("CONFIG_TEST", &["CONFIG_TEST"]).is_err())
     }
 
     #[test]
     fn dependencies_cannot_include_duplciates() {
         let mut graph: Graph<&str, state::Incomplete> = Graph::new();
-        assert!(graph.insert(&"CONFIG_TEST", &[&"CONFIG_ANOTHER", &"CONFIG_ANOTHER"]).is_err());
+        assert!(graph.insert("CONFIG_TEST", &["CONFIG_ANOTHER", "CONFIG_ANOTHER"]).is_err());
     }
 
     #[test]
     fn single_dependent_node_found() -> Result<(), Box<dyn error::Error>> {
         let mut graph: Graph<&str, state::Incomplete> = Graph::new();
-        graph.insert(&"CONFIG_PARENT", &[])?;
-        graph.insert(&"CONFIG_CHILD", &["CONFIG_PARENT"])?;
+        graph.insert("CONFIG_PARENT", &[])?;
+        graph.insert("CONFIG_CHILD", &["CONFIG_PARENT"])?;
         let graph = graph.into_complete()?;
         let dependent = graph.dependent_vertices(&"CONFIG_PARENT")?;
         assert_eq!(dependent.len(), 1);
@@ -372,9 +745,9 @@ mod tests {
         let opts = ["CONFIG0", "CONFIG1", "CONFIG2", "CONFIG3"];
         if let Some((last, rest)) = opts.split_last() {
             for (i, opt) in rest.iter().enumerate() {
-                graph.insert(&opt, &[&opts[i + 1]])?;
+                graph.insert(opt, &[opts[i + 1]])?;
             }
-            graph.insert(&last, &[])?;
+            graph.insert(last, &[])?;
         }
         let graph = graph.into_complete()?;
         let mut dependent = graph.dependent_vertices(&"CONFIG3")?;
@@ -387,9 +760,9 @@ mod tests {
     fn multiple_independent_children() -> Result<(), Box<dyn error::Error>> {
         let mut graph: Graph<&str, state::Incomplete> = Graph::new();
         let opts = ["CONFIG_CHILD0", "CONFIG_CHILD1", "CONFIG_CHILD2"];
-        graph.insert(&"CONFIG_PARENT", &[])?;
+        graph.insert("CONFIG_PARENT", &[])?;
         for opt in opts {
-            graph.insert(&opt, &[&"CONFIG_PARENT"])?;
+            graph.insert(opt, &["CONFIG_PARENT"])?;
         }
         let graph = graph.into_complete()?;
         let mut dependent = graph.dependent_vertices(&"CONFIG_PARENT")?;
@@ -397,4 +770,189 @@ mod tests {
         assert_eq!(dependent, opts);
         Ok(())
     }
+
+    #[test]
+    fn direct_edges_exclude_transitive_vertices() -> Result<(), Box<dyn error::Error>> {
+        let mut graph: Graph<&str, state::Incomplete> = Graph::new();
+        graph.insert("CONFIG0", &[])?;
+        graph.insert("CONFIG1", &["CONFIG0"])?;
+        graph.insert("CONFIG2", &["CONFIG1"])?;
+        let graph = graph.into_complete()?;
+        assert_eq!(graph.direct_dependents_of(&"CONFIG0")?, vec!["CONFIG1"]);
+        assert_eq!(graph.direct_dependencies_of(&"CONFIG2")?, vec!["CONFIG1"]);
+        assert_eq!(graph.dependent_vertices(&"CONFIG0")?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn cycles_are_reported_with_their_path() -> Result<(), Box<dyn error::Error>> {
+        let mut graph: Graph<&str, state::Incomplete> = Graph::new();
+        graph.insert("CONFIG_ROOT", &[])?;
+        graph.insert("CONFIG_A", &["CONFIG_ROOT", "CONFIG_B"])?;
+        graph.insert("CONFIG_B", &["CONFIG_C"])?;
+        graph.insert("CONFIG_C", &["CONFIG_A"])?;
+        let err = graph.into_complete().unwrap_err();
+        assert_eq!(err.to_string(), "Dependency cycle: CONFIG_A -> CONFIG_B -> CONFIG_C -> CONFIG_A");
+        Ok(())
+    }
+
+    fn diamond() -> Result<Graph<&'static str, state::Complete>, Box<dyn error::Error>> {
+        let mut graph: Graph<&str, state::Incomplete> = Graph::new();
+        graph.insert("CONFIG_TOP", &["CONFIG_LEFT", "CONFIG_RIGHT"])?;
+        graph.insert("CONFIG_LEFT", &["CONFIG_BASE"])?;
+        graph.insert("CONFIG_RIGHT", &["CONFIG_MID"])?;
+        graph.insert("CONFIG_MID", &["CONFIG_BASE"])?;
+        graph.insert("CONFIG_BASE", &[])?;
+        graph.insert("CONFIG_ALONE", &[])?;
+        graph.into_complete()
+    }
+
+    #[test]
+    fn topological_order_places_dependencies_first() -> Result<(), Box<dyn error::Error>> {
+        let graph = diamond()?;
+        assert_eq!(graph.topological_order(),
+                   vec!["CONFIG_BASE", "CONFIG_LEFT", "CONFIG_MID", "CONFIG_RIGHT", "CONFIG_TOP", "CONFIG_ALONE"]);
+        assert_eq!(graph.roots(), vec!["CONFIG_BASE", "CONFIG_ALONE"]);
+        assert_eq!(graph.leaves(), vec!["CONFIG_TOP", "CONFIG_ALONE"]);
+        Ok(())
+    }
+
+    #[test]
+    fn depth_is_longest_path_to_a_root() -> Result<(), Box<dyn error::Error>> {
+        let graph = diamond()?;
+        assert_eq!(graph.depth_of(&"CONFIG_BASE")?, 0);
+        assert_eq!(graph.depth_of(&"CONFIG_LEFT")?, 1);
+        assert_eq!(graph.depth_of(&"CONFIG_TOP")?, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn shortest_path_follows_dependencies() -> Result<(), Box<dyn error::Error>> {
+        let graph = diamond()?;
+        assert_eq!(graph.shortest_path(&"CONFIG_TOP", &"CONFIG_BASE")?,
+                   Some(vec!["CONFIG_TOP", "CONFIG_LEFT", "CONFIG_BASE"]));
+        assert_eq!(graph.shortest_path(&"CONFIG_BASE", &"CONFIG_TOP")?, None);
+        assert_eq!(graph.shortest_path(&"CONFIG_MID", &"CONFIG_MID")?, Some(vec!["CONFIG_MID"]));
+        Ok(())
+    }
+
+    #[test]
+    fn removed_vertices_leave_dangling_edges() -> Result<(), Box<dyn error::Error>> {
+        let mut graph = diamond()?.into_incomplete();
+        graph.remove(&"CONFIG_MID")?;
+        assert_eq!(graph.dangling_edges(), vec![("CONFIG_RIGHT", "CONFIG_MID")]);
+        graph.relink(&"CONFIG_RIGHT", &["CONFIG_BASE"])?;
+        assert!(graph.dangling_edges().is_empty());
+
+        let graph = graph.into_complete()?;
+        assert_eq!(graph.dependencies_of(&"CONFIG_TOP")?, vec!["CONFIG_LEFT", "CONFIG_RIGHT", "CONFIG_BASE"]);
+        assert_eq!(graph.direct_dependents_of(&"CONFIG_BASE")?, vec!["CONFIG_LEFT", "CONFIG_RIGHT"]);
+        Ok(())
+    }
+
+    #[test]
+    fn renamed_vertices_keep_their_edges() -> Result<(), Box<dyn error::Error>> {
+        let mut graph = diamond()?.into_incomplete();
+        graph.insert("CONFIG_NEW", &["CONFIG_CORE"])?;
+        graph.rename(&"CONFIG_BASE", "CONFIG_CORE")?;
+        assert!(graph.rename(&"CONFIG_LEFT", "CONFIG_TOP").is_err());
+
+        let graph = graph.into_complete()?;
+        assert_eq!(graph.direct_dependencies_of(&"CONFIG_MID")?, vec!["CONFIG_CORE"]);
+        assert_eq!(graph.direct_dependents_of(&"CONFIG_CORE")?, vec!["CONFIG_LEFT", "CONFIG_MID", "CONFIG_NEW"]);
+        Ok(())
+    }
+
+    #[test]
+    fn relinking_can_introduce_cycles() -> Result<(), Box<dyn error::Error>> {
+        let mut graph = diamond()?.into_incomplete();
+        graph.relink(&"CONFIG_BASE", &["CONFIG_TOP"])?;
+        assert!(graph.relink(&"CONFIG_BASE", &["CONFIG_BASE"]).is_err());
+        assert!(graph.into_complete().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn traversals_filter_edges_by_kind() -> Result<(), Box<dyn error::Error>> {
+        let mut graph = Graph::new();
+        graph.insert("CONFIG_MENU", &[])?;
+        graph.insert_edges("CONFIG_NET", &[("CONFIG_MENU", EdgeKind::Contains)])?;
+        graph.insert_edges("CONFIG_TLS", &[("CONFIG_NET", EdgeKind::Depends), ("CONFIG_MENU", EdgeKind::Contains)])?;
+        graph.insert_edges("CONFIG_HTTP", &[("CONFIG_TLS", EdgeKind::Selects), ("CONFIG_NET", EdgeKind::Depends)])?;
+        graph.insert_edges("CONFIG_PLAIN", &[("CONFIG_TLS", EdgeKind::Conflicts)])?;
+        let graph = graph.into_complete()?;
+
+        assert_eq!(graph.edge_kind(&"CONFIG_HTTP", &"CONFIG_TLS")?, Some(EdgeKind::Selects));
+        assert_eq!(graph.edge_kind(&"CONFIG_TLS", &"CONFIG_HTTP")?, None);
+        assert_eq!(graph.dependencies_of(&"CONFIG_HTTP")?, vec!["CONFIG_TLS", "CONFIG_NET"]);
+        assert_eq!(graph.dependent_vertices(&"CONFIG_MENU")?, Vec::<&str>::new());
+        assert_eq!(graph.direct_dependents_of(&"CONFIG_TLS")?, vec!["CONFIG_HTTP"]);
+        assert_eq!(graph.dependencies_of_kind(&"CONFIG_HTTP", &[EdgeKind::Depends, EdgeKind::Selects])?,
+                   vec!["CONFIG_TLS", "CONFIG_NET"]);
+        assert_eq!(graph.direct_dependencies_of_kind(&"CONFIG_TLS", &[EdgeKind::Contains])?, vec!["CONFIG_MENU"]);
+        assert_eq!(graph.dependent_vertices_of_kind(&"CONFIG_NET", &[EdgeKind::Depends])?, vec!["CONFIG_TLS", "CONFIG_HTTP"]);
+        assert_eq!(graph.direct_dependents_of_kind(&"CONFIG_TLS", &[EdgeKind::Conflicts])?, vec!["CONFIG_PLAIN"]);
+        Ok(())
+    }
+
+    #[test]
+    fn relinked_edges_keep_their_kind() -> Result<(), Box<dyn error::Error>> {
+        let mut graph = diamond()?.into_incomplete();
+        graph.relink_edges(&"CONFIG_TOP", &[("CONFIG_LEFT", EdgeKind::Implies), ("CONFIG_RIGHT", EdgeKind::Depends)])?;
+        graph.remove(&"CONFIG_RIGHT")?;
+        graph.insert("CONFIG_RIGHT", &["CONFIG_MID"])?;
+
+        let graph = graph.into_complete()?;
+        assert_eq!(graph.edge_kind(&"CONFIG_TOP", &"CONFIG_LEFT")?, Some(EdgeKind::Implies));
+        assert_eq!(graph.edge_kind(&"CONFIG_TOP", &"CONFIG_RIGHT")?, Some(EdgeKind::Depends));
+        assert_eq!(graph.dependencies_of_kind(&"CONFIG_TOP", &[EdgeKind::Depends])?,
+                   vec!["CONFIG_RIGHT", "CONFIG_MID", "CONFIG_BASE"]);
+        Ok(())
+    }
+
+    #[test]
+    fn only_requiring_edges_form_cycles() -> Result<(), Box<dyn error::Error>> {
+        let mut graph = Graph::new();
+        graph.insert_edges("CONFIG_TLS", &[("CONFIG_PLAIN", EdgeKind::Conflicts)])?;
+        graph.insert_edges("CONFIG_PLAIN", &[("CONFIG_TLS", EdgeKind::Conflicts), ("CONFIG_NET", EdgeKind::Depends)])?;
+        graph.insert_edges("CONFIG_NET", &[("CONFIG_PLAIN", EdgeKind::Implies)])?;
+        let graph = graph.into_complete()?;
+        assert_eq!(graph.topological_order(), vec!["CONFIG_TLS", "CONFIG_NET", "CONFIG_PLAIN"]);
+        assert_eq!(graph.roots(), vec!["CONFIG_TLS", "CONFIG_NET"]);
+        assert_eq!(graph.depth_of(&"CONFIG_PLAIN")?, 1);
+
+        let mut graph = graph.into_incomplete();
+        graph.relink_edges(&"CONFIG_NET", &[("CONFIG_PLAIN", EdgeKind::Selects)])?;
+        assert!(graph.into_complete().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn closure_order_does_not_depend_on_earlier_queries() -> Result<(), Box<dyn error::Error>> {
+        let build = || -> Result<Graph<&str, state::Complete>, Box<dyn error::Error>> {
+            let mut graph: Graph<&str, state::Incomplete> = Graph::new();
+            graph.insert("E", &[])?;
+            graph.insert("D", &["E"])?;
+            graph.insert("C", &["E"])?;
+            graph.insert("B", &["D"])?;
+            graph.insert("A", &["B", "C"])?;
+            graph.into_complete()
+        };
+
+        let first = build()?;
+        let a_first = first.dependencies_of(&"A")?;
+        let b_second = first.dependencies_of(&"B")?;
+        let second = build()?;
+        let b_first = second.dependencies_of(&"B")?;
+        let a_second = second.dependencies_of(&"A")?;
+        assert_eq!(a_first, vec!["B", "C", "D", "E"]);
+        assert_eq!(a_first, a_second);
+        assert_eq!(b_first, b_second);
+
+        let e_first = first.dependent_vertices(&"E")?;
+        first.dependent_vertices(&"D")?;
+        second.dependent_vertices(&"D")?;
+        assert_eq!(e_first, second.dependent_vertices(&"E")?);
+        Ok(())
+    }
 }
@@ -4,9 +4,9 @@
     rustdoc::broken_intra_doc_links
 )]
 
-pub use crate::list::ListOp;
+pub use crate::changeset::Review;
+pub use crate::list::{Direction, ListOp};
 use std::{error, fmt, path};
-use serde;
 
 /// Command line management
 pub mod cli;
@@ -26,6 +26,39 @@ pub mod display_vec;
 pub mod logger;
 /// Config generation
 pub mod generate;
+/// Language bindings generation
+pub mod bindings;
+/// Template rendering
+pub mod template;
+/// Writing of generated files
+pub mod output;
+/// Per-option output files
+pub mod split;
+/// Config upgrades across specification changes
+pub mod upgrade;
+/// Full-screen terminal interface
+pub mod menu;
+/// Bulk config generators
+pub mod bulk;
+/// Merging of config fragments
+pub mod merge;
+/// Semantic comparison of configs
+pub mod diff;
+/// Comparison of specifications
+pub mod specdiff;
+/// Constraint solving for dependency resolution
+pub mod solver;
+/// Explanation of option values
+pub mod why;
+/// Dependency diagrams
+pub mod diagram;
+/// In-memory config documents
+pub mod config;
+/// Reviewable changes made by config manipulations
+pub mod changeset;
+/// Fixtures shared by unit tests
+#[cfg(test)]
+mod test_util;
 
 #[derive(Debug)]
 pub struct State {
@@ -42,11 +75,6 @@ pub struct State {
     pub mode: Mode
 }
 
-#[derive(Debug)]
-pub enum ConfType {
-    Defconfig
-}
-
 #[derive(Debug)]
 pub enum Mode {
     List {
@@ -54,17 +82,55 @@ pub enum Mode {
     },
     Validate,
     Enable {
-        option: String
+        option: String,
+        review: Review
     },
     Disable {
-        option: String
+        option: String,
+        review: Review
     },
     Set {
         option: String,
-        value: String
+        value: String,
+        review: Review
     },
     Generate {
-        conftype: ConfType
+        generator: String,
+        output: Option<path::PathBuf>,
+        check: bool,
+        params: Vec<(String, String)>
+    },
+    ListGenerators,
+    Olddefconfig,
+    Oldconfig,
+    Menu,
+    Merge {
+        fragments: Vec<path::PathBuf>,
+        output: Option<path::PathBuf>
+    },
+    Diff {
+        old: path::PathBuf,
+        new: path::PathBuf,
+        json: bool
+    },
+    SpecDiff {
+        old: path::PathBuf,
+        new: path::PathBuf,
+        markdown: bool
+    },
+    Why {
+        option: String
+    },
+    Graph {
+        format: diagram::Format,
+        root: Option<String>,
+        depth: Option<usize>,
+        values: bool
+    },
+    Render {
+        template: path::PathBuf,
+        output: path::PathBuf,
+        check: bool
     }
 }
 
@@ -94,13 +160,19 @@ impl ConfigEntry {
         }
     }
 
-    pub fn is_switch(&self) -> bool {
+    /// Name of the entry type as used in the specification
+    pub fn type_name(&self) -> &'static str {
         match &self.enttype {
-            EntryType::Switch(_) => true,
-            _ => false
+            EntryType::Switch(_) => "switch",
+            EntryType::String(_) => "string",
+            EntryType::Int(_) => "integer"
         }
     }
 
+    pub fn is_switch(&self) -> bool {
+        matches!(&self.enttype, EntryType::Switch(_))
+    }
+
     pub fn is_enabled_by_default(&self) -> Result<bool, Box<dyn error::Error>> {
         match &self.enttype {
             EntryType::Switch(Switch::Yes) => Ok(true),
@@ -118,21 +190,17 @@ pub enum Switch {
 
 impl fmt::Display for ConfigEntry {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}:\n", self.name)?;
-        write!(f, "  depends: {}\n", self.depends)?;
-        let enttype = match &self.enttype {
-            EntryType::Switch(_) => "switch",
-            EntryType::String(_) => "string",
-            EntryType::Int(_) => "integer"
-        };
+        writeln!(f, "{}:", self.name)?;
+        writeln!(f, "  depends: {}", self.depends)?;
+        let enttype = self.type_name();
 
-        write!(f, "  type: {}\n", enttype)?;
+        writeln!(f, "  type: {}", enttype)?;
         write!(f, "  choices: ")?;
         match &self.choices {
-            Some(choices) => write!(f, "{}\n", choices)?,
+            Some(choices) => writeln!(f, "{}", choices)?,
             None => match &self.enttype {
-                EntryType::Switch(_) => write!(f, "y, n\n")?,
-                _ => write!(f, "Any {}\n", enttype)?
+                EntryType::Switch(_) => writeln!(f, "y, n")?,
+                _ => writeln!(f, "Any {}", enttype)?
             }
         };
         let default = match &self.enttype {
@@ -141,7 +209,7 @@ impl fmt::Display for ConfigEntry {
             EntryType::String(s) => s.to_string(),
             EntryType::Int(i) => i.to_string()
         };
-        write!(f, "  default: {}\n", default)?;
+        writeln!(f, "  default: {}", default)?;
         write!(f, "  help: {}", self.help)?;
         Ok(())
     }
@@ -6,7 +6,17 @@ use std::error;
 pub enum ListOp {
     Show(String),
     All,
-    Dependencies(String)
+    Dependencies(String),
+    Dependents(String),
+    /// Tree of dependencies or dependents, optionally limited in depth
+    Tree(String, Direction, Option<usize>)
+}
+
+/// Which edges a dependency tree follows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Dependencies,
+    Dependents
 }
 
 pub fn show(option: &str, entries: &[ConfigEntry]) -> Result<(), Box<dyn error::Error>> {
@@ -26,12 +36,10 @@ pub fn show_all(entries: &Vec<ConfigEntry>) {
     }
 }
 
-pub fn dependencies(option: &str, entries: &[ConfigEntry]) -> Result<(), Box<dyn error::Error>> {
-    let graph = Graph::<&str, state::Incomplete>::from(entries);
-    let graph = graph.into_complete()?;
+pub fn dependencies(option: &str, graph: &Graph<&str, state::Complete>) -> Result<(), Box<dyn error::Error>> {
     let deps = graph.dependencies_of(&option)?;
     println!("{}:", option);
-    if deps.len() == 0usize {
+    if deps.is_empty() {
         println!("  None");
     }
     else {
@@ -42,3 +50,159 @@ pub fn dependencies(option: &str, entries: &[ConfigEntry]) -> Result<(), Box<dyn
 
     Ok(())
 }
+
+/// Render the options depending on `option`, separating direct from transitive dependents
+pub fn render_dependents(option: &str, graph: &Graph<&str, state::Complete>) -> Result<String, Box<dyn error::Error>> {
+    let direct = graph.direct_dependents_of(&option)?;
+    let transitive: Vec<&str> = graph.dependent_vertices(&option)?
+                                     .into_iter()
+                                     .filter(|d| !direct.contains(d))
+                                     .collect();
+    let mut out = format!("{}:\n", option);
+    if direct.is_empty() {
+        out.push_str("  None\n");
+        return Ok(out);
+    }
+
+    out.push_str("  Direct:\n");
+    for dep in &direct {
+        out.push_str(&format!("    {}\n", dep));
+    }
+    if !transitive.is_empty() {
+        out.push_str("  Transitive:\n");
+        for dep in &transitive {
+            out.push_str(&format!("    {}\n", dep));
+        }
+    }
+
+    Ok(out)
+}
+
+/// List options depending on `option`, separating direct from transitive dependents
+pub fn dependents(option: &str, graph: &Graph<&str, state::Complete>) -> Result<(), Box<dyn error::Error>> {
+    print!("{}", render_dependents(option, graph)?);
+    Ok(())
+}
+
+fn tree_children<'a>(graph: &Graph<&'a str, state::Complete>, option: &'a str, direction: Direction)
+    -> Result<Vec<&'a str>, Box<dyn error::Error>>
+{
+    match direction {
+        Direction::Dependencies => graph.direct_dependencies_of(&option),
+        Direction::Dependents => graph.direct_dependents_of(&option)
+    }
+}
+
+fn render_subtree<'a>(graph: &Graph<&'a str, state::Complete>, option: &'a str, direction: Direction,
+                      depth: Option<usize>, prefix: &str, expanded: &mut Vec<&'a str>, out: &mut String)
+    -> Result<(), Box<dyn error::Error>>
+{
+    let children = tree_children(graph, option, direction)?;
+    for (i, &child) in children.iter().enumerate() {
+        let last = i + 1 == children.len();
+        let (branch, indent) = if last { ("`-- ", "    ") } else { ("|-- ", "|   ") };
+        out.push_str(&format!("{}{}{}", prefix, branch, child));
+
+        let grandchildren = tree_children(graph, child, direction)?;
+        if grandchildren.is_empty() {
+            out.push('\n');
+        }
+        else if expanded.contains(&child) {
+            out.push_str(" (see above)\n");
+        }
+        else if depth == Some(1) {
+            out.push_str(" ...\n");
+        }
+        else {
+            out.push('\n');
+            expanded.push(child);
+            render_subtree(graph, child, direction, depth.map(|d| d - 1), &format!("{}{}", prefix, indent),
+                           expanded, out)?;
+        }
+    }
+    Ok(())
+}
+
+/// Render the dependencies or dependents of `option` as an ASCII tree
+///
+/// Subtrees that were already shown are only marked with "(see above)", and
+/// branches cut off by `depth` end in "...".
+pub fn render_tree<'a>(option: &str, direction: Direction, depth: Option<usize>, entries: &'a [ConfigEntry],
+                       graph: &Graph<&'a str, state::Complete>)
+    -> Result<String, Box<dyn error::Error>>
+{
+    let root = match entries.iter().find(|e| e.name == option) {
+        Some(ent) => ent.name.as_str(),
+        None => return Err(format!("Invalid config option {}", option).into())
+    };
+
+    let mut out = format!("{}\n", root);
+    if depth != Some(0) {
+        render_subtree(graph, root, direction, depth, "", &mut vec![root], &mut out)?;
+    }
+    Ok(out)
+}
+
+pub fn tree(option: &str, direction: Direction, depth: Option<usize>, entries: &[ConfigEntry],
+            graph: &Graph<&str, state::Complete>)
+    -> Result<(), Box<dyn error::Error>>
+{
+    print!("{}", render_tree(option, direction, depth, entries, graph)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::list::*;
+    use crate::test_util::entry;
+    use crate::{parse, EntryType, Switch};
+
+    fn entries() -> Vec<ConfigEntry> {
+        let switch = |name, depends| entry(name, depends, EntryType::Switch(Switch::Yes));
+        vec![
+            switch("CONFIG_BASE", &[]),
+            switch("CONFIG_BUS", &["CONFIG_BASE"]),
+            switch("CONFIG_NET", &["CONFIG_BUS"]),
+            switch("CONFIG_USB", &["CONFIG_BUS"]),
+            switch("CONFIG_MODEM", &["CONFIG_NET", "CONFIG_USB"])
+        ]
+    }
+
+    #[test]
+    fn shared_subtrees_are_shown_once() -> Result<(), Box<dyn error::Error>> {
+        let entries = entries();
+        let tree = render_tree("CONFIG_MODEM", Direction::Dependencies, None, &entries, &parse::spec_graph(&entries)?)?;
+        assert_eq!(tree, "CONFIG_MODEM\n\
+                          |-- CONFIG_NET\n\
+                          |   `-- CONFIG_BUS\n\
+                          |       `-- CONFIG_BASE\n\
+                          `-- CONFIG_USB\n\
+                          \x20   `-- CONFIG_BUS (see above)\n");
+        Ok(())
+    }
+
+    #[test]
+    fn dependents_tree_is_depth_limited() -> Result<(), Box<dyn error::Error>> {
+        let entries = entries();
+        let tree = render_tree("CONFIG_BASE", Direction::Dependents, Some(2), &entries, &parse::spec_graph(&entries)?)?;
+        assert_eq!(tree, "CONFIG_BASE\n\
+                          `-- CONFIG_BUS\n\
+                          \x20   |-- CONFIG_NET ...\n\
+                          \x20   `-- CONFIG_USB ...\n");
+        Ok(())
+    }
+
+    #[test]
+    fn dependents_are_split_into_direct_and_transitive() -> Result<(), Box<dyn error::Error>> {
+        let entries = entries();
+        let graph = parse::spec_graph(&entries)?;
+        assert_eq!(render_dependents("CONFIG_BUS", &graph)?, "CONFIG_BUS:\n  \
+                                                              Direct:\n    \
+                                                              CONFIG_NET\n    \
+                                                              CONFIG_USB\n  \
+                                                              Transitive:\n    \
+                                                              CONFIG_MODEM\n");
+        assert_eq!(render_dependents("CONFIG_MODEM", &graph)?, "CONFIG_MODEM:\n  None\n");
+        Ok(())
+    }
+}
@@ -1,148 +1,288 @@
 use regex::Regex;
-use std::{error, fmt, fs, path};
-use std::io::Write;
-use crate::{parse, ConfigEntry, EntryType, Switch};
-use crate::graph::{state, Graph};
+use std::{collections, error, fmt, io, path};
+use crate::{generate, parse, ConfigEntry, EntryType, Switch};
+use crate::changeset::{ChangeSet, OptionChange, Reason, Review};
+use crate::generate::Resolved;
+use crate::graph::{state, EdgeKind, Graph};
 use crate::logger::{Logger, Verbosity};
+use crate::output::{self, WriteMode};
+use crate::solver::{Constraint, Model};
 
-fn enable_dependencies(opt: &str, kvpairs: &mut Vec<(String, String)>, entries: &[ConfigEntry], log: &Logger)
-    -> Result<(), Box<dyn error::Error>>
-{
-    log.writeln(Verbosity::Lvl1, &format!("Enabling dependencies for {}", opt));
-    let graph = Graph::<&str, state::Incomplete>::from(entries);
-    let graph = graph.into_complete()?;
-    let deps = match graph.dependencies_of(&opt) {
-        Ok(deps) => deps,
-        Err(_) => return Err(format!("Invalid config option \"{}\"", opt).into())
-    };
-
-    log.writeln(Verbosity::Lvl2, &format!("Dependencies for {}: {:?}", opt, deps));
+/// Edge kinds the solver turns into constraints
+const MODELLED: [EdgeKind; 3] = [EdgeKind::Depends, EdgeKind::Selects, EdgeKind::Conflicts];
 
-    for dep in &deps {
-        if let Some((_, v)) = kvpairs.iter_mut().find(|(k, _)| k == dep) {
-            log.writeln(Verbosity::Lvl2, &format!("Enabling existing dependency \"{}\"", dep));
-            *v = "y".to_string();
-        }
-        else {
-            log.writeln(Verbosity::Lvl2, &format!("Adding missing depdency \"{}\"", dep));
-            kvpairs.push((dep.to_string(), "y".to_string()));
-        }
+fn is_enabled(res: &Resolved<'_>) -> bool {
+    match (res.entry.is_switch(), &res.value) {
+        (true, Some(value)) => value == "y",
+        (_, value) => value.is_some()
     }
-
-    Ok(())
 }
 
-fn disable_dependent(opt: &str, kvpairs: &mut Vec<(String, String)>, entries: &[ConfigEntry], log: &Logger)
-    -> Result<(), Box<dyn error::Error>>
+/// Changes setting `opt` to `value` in `kvpairs`, removing it for `None`, and
+/// moving the rest to the closest config in which all dependencies hold
+///
+/// Options start out in the state the config resolves to, defaults included.
+/// Only the dependencies and dependents of `opt` may change, switches being
+/// enabled or disabled and other options added with their default value or
+/// removed. The requested option comes first, followed by the others in
+/// specification order.
+fn satisfy(opt: &str, value: Option<&str>, kvpairs: &[(String, String)], entries: &[ConfigEntry],
+           graph: &Graph<&str, state::Complete>, log: &Logger)
+    -> Result<ChangeSet, Box<dyn error::Error>>
 {
-    log.writeln(Verbosity::Lvl1, &format!("Disabling options depending on {}", opt));
-    let graph = Graph::<&str, state::Incomplete>::from(entries);
-    let graph = graph.into_complete()?;
-    let dependent = match graph.dependent_vertices(&opt) {
-        Ok(dependent) => dependent,
-        Err(_) => return Err(format!("Invalid config option \"{}\"", opt).into())
+    let ent = match entries.iter().find(|e| e.name == opt) {
+        Some(ent) => ent,
+        None => return Err(format!("Invalid config option \"{}\"", opt).into())
+    };
+    let current: collections::HashMap<&str, bool> = generate::resolve(kvpairs, entries, graph, log)?
+        .into_iter()
+        .map(|r| (r.entry.name.as_str(), is_enabled(&r)))
+        .collect();
+    let enabled = match value {
+        Some(value) => !ent.is_switch() || value == "y",
+        None => {
+            // Without a value the option falls back to its default
+            let unset: Vec<(String, String)> = kvpairs.iter()
+                                                      .filter(|(k, _)| k != opt)
+                                                      .cloned()
+                                                      .collect();
+            generate::resolve(&unset, entries, graph, log)?
+                .iter()
+                .any(|r| r.entry.name == opt && is_enabled(r))
+        }
     };
 
-    log.writeln(Verbosity::Lvl2, &format!("Options depending on {}: {:?}", opt, dependent));
-
-    for dep in &dependent {
-        let ent = entries.iter().find(|e| e.name == *dep).unwrap();
-        match ent.enttype {
-            EntryType::Switch(_) => {
-                if let Some((_, v)) = kvpairs.iter_mut().find(|(k, _)| k == dep) {
-                    log.writeln(Verbosity::Lvl2, &format!("Disabling exising option \"{}\"", dep));
-                    *v = "n".to_string();
-                }
-                else {
-                    log.writeln(Verbosity::Lvl2, &format!("Disabling missing option \"{}\"", dep));
-                    kvpairs.push((dep.to_string(), "n".to_string()));
-                }
+    let deps = graph.dependencies_of(&opt)?;
+    let mut scope: collections::HashSet<&str> = deps.iter().copied().collect();
+    scope.extend(graph.dependent_vertices(&opt)?);
+    scope.insert(opt);
+    // Options next to the scope are part of the model, but keep their state
+    let mut neighbours: collections::HashSet<&str> = collections::HashSet::new();
+    for option in &scope {
+        neighbours.extend(graph.direct_dependencies_of_kind(option, &MODELLED)?);
+        neighbours.extend(graph.direct_dependents_of_kind(option, &MODELLED)?);
+    }
+    let modelled: Vec<&ConfigEntry> = entries.iter()
+                                             .filter(|e| scope.contains(e.name.as_str())
+                                                         || neighbours.contains(e.name.as_str()))
+                                             .collect();
+    let options: Vec<&str> = modelled.iter().map(|e| e.name.as_str()).collect();
+    let model = Model::from_graph(&options, graph)?;
+
+    let state: Vec<bool> = options.iter().map(|o| current[o]).collect();
+    let mut requests = vec![Constraint::Fixed { option: opt.to_string(), enabled }];
+    requests.extend(options.iter()
+                           .filter(|o| !scope.contains(*o))
+                           .map(|o| Constraint::Fixed { option: o.to_string(), enabled: current[o] }));
+    log.writeln(Verbosity::Lvl1, &format!("Solving {} constraints over {} options",
+                                          model.constraints().len() + requests.len(), options.len()));
+    let solution = model.solve(&state, &requests, log)?;
+
+    let listed: collections::HashMap<&str, &str> = kvpairs.iter()
+                                                          .map(|(k, v)| (k.as_str(), v.as_str()))
+                                                          .collect();
+    let mut changes = vec![];
+    changes.extend(OptionChange::new(opt, listed.get(opt).copied(), value.map(|v| v.to_string()),
+                                     Reason::Requested));
+    for (ent, (old, new)) in modelled.iter().zip(state.iter().zip(&solution)) {
+        let name = ent.name.as_str();
+        if name == opt || !scope.contains(name) {
+            continue;
+        }
+        // Validation expects the dependencies of an enabled option to be listed
+        let unlisted = enabled && *new && deps.contains(&name) && !listed.contains_key(name);
+        if old == new && !unlisted {
+            continue;
+        }
+        let update = match (ent.is_switch(), new) {
+            (true, _) => {
+                let value = if *new { "y" } else { "n" };
+                log.writeln(Verbosity::Lvl2, &format!("Setting \"{}\" to \"{}\" to satisfy dependencies", name, value));
+                Some(value.to_string())
+            },
+            (false, true) => {
+                log.writeln(Verbosity::Lvl2, &format!("Adding dependency \"{}\" with its default value", name));
+                Some(ent.default_value())
             },
-            _ => {
-                log.writeln(Verbosity::Lvl2, &format!("Removing dependent option \"{}\"", dep));
-                kvpairs.retain(|(k, _)| k != dep);
+            (false, false) => {
+                log.writeln(Verbosity::Lvl2, &format!("Removing dependent option \"{}\"", name));
+                None
             }
-        }
+        };
+        // Dependencies are only ever enabled and dependents disabled, the
+        // scope holding nothing else
+        let reason = match deps.contains(&name) {
+            true => Reason::Dependency,
+            false => Reason::Dependent
+        };
+        changes.extend(OptionChange::new(name, listed.get(name).copied(), update, reason));
     }
-
-    Ok(())
+    Ok(ChangeSet::new(changes))
 }
 
-pub fn write_config<T>(kvpairs: &Vec<(T, String)>, path: &path::PathBuf, log: &Logger)
-    -> Result<(), Box<dyn error::Error>>
+/// Format key-value pairs as config file contents
+pub fn format_config<T>(kvpairs: &[(T, String)], log: &Logger) -> String
 where
     T: AsRef<str> + fmt::Display
 {
-    let mut f = fs::File::create(path)?;
-    log.writeln(Verbosity::Lvl2, &format!("Writing config to {}", path.to_str().unwrap()));
     let pad = kvpairs.iter()
                      .map(|(k, _)| k.as_ref().len())
                      .max()
-                     .unwrap();
+                     .unwrap_or(0);
     log.writeln(Verbosity::Lvl3, &format!("Padding keys to {} chars", pad));
-    for (k, v) in kvpairs {
-        f.write_all(format!("{:width$} = {}\n", k, v, width=pad).as_ref())?;
-    }
+    kvpairs.iter()
+           .map(|(k, v)| format!("{:width$} = {}\n", k, v, width=pad))
+           .collect()
+}
+
+/// Write config, leaving the file untouched if its contents would not change
+pub fn write_config<T>(kvpairs: &[(T, String)], path: &path::Path, log: &Logger)
+    -> Result<(), Box<dyn error::Error>>
+where
+    T: AsRef<str> + fmt::Display
+{
+    log.writeln(Verbosity::Lvl2, &format!("Writing config to {}", path.to_str().unwrap()));
+    output::write(path, &format_config(kvpairs, log), WriteMode::Write, log)?;
     Ok(())
 }
 
-fn set_switch(opt: &str, desired: Switch, kvpairs: &mut Vec<(String, String)>, entries: &[ConfigEntry]) -> Result<(), Box<dyn error::Error>> {
+/// Changes setting switch `opt` to `desired`
+fn switch_changes(opt: &str, desired: Switch, kvpairs: &[(String, String)], entries: &[ConfigEntry],
+                  graph: &Graph<&str, state::Complete>, log: &Logger)
+    -> Result<ChangeSet, Box<dyn error::Error>>
+{
     let ent = match entries.iter().find(|e| e.name == opt) {
         Some(ent) => ent,
         None => return Err(format!("Invalid config option \"{}\"", opt).into())
     };
 
+    let action = match desired {
+        Switch::Yes => "enable",
+        Switch::No => "disable"
+    };
     match ent.enttype {
         EntryType::Switch(_) => (),
-        _ => {
-            let action = match desired {
-                Switch::Yes => "enable",
-                Switch::No => "disable"
-            };
-            return Err(format!("Cannot {} non-switch option \"{}\"", action, opt).into())
-        }
+        _ => return Err(format!("Cannot {} non-switch option \"{}\"", action, opt).into())
     };
 
     let desired = match desired {
-        Switch::Yes => "y".to_string(),
-        Switch::No => "n".to_string()
+        Switch::Yes => "y",
+        Switch::No => "n"
     };
+    satisfy(opt, Some(desired), kvpairs, entries, graph, log)
+}
 
-    if let Some((_, v)) = kvpairs.iter_mut().find(|(k, _)| k == opt) {
-        *v = desired;
-    }
-    else {
-        kvpairs.push((opt.to_string(), desired));
-    }
+/// Changes [`enable_option`] would make to `kvpairs`
+pub fn enable_changes(opt: &str, kvpairs: &[(String, String)], entries: &[ConfigEntry],
+                      graph: &Graph<&str, state::Complete>, log: &Logger)
+    -> Result<ChangeSet, Box<dyn error::Error>>
+{
+    log.writeln(Verbosity::Lvl1, &format!("Enabling switch {}", opt));
+    switch_changes(opt, Switch::Yes, kvpairs, entries, graph, log)
+}
+
+/// Changes [`disable_option`] would make to `kvpairs`
+pub fn disable_changes(opt: &str, kvpairs: &[(String, String)], entries: &[ConfigEntry],
+                       graph: &Graph<&str, state::Complete>, log: &Logger)
+    -> Result<ChangeSet, Box<dyn error::Error>>
+{
+    log.writeln(Verbosity::Lvl1, &format!("Disabling switch {}", opt));
+    switch_changes(opt, Switch::No, kvpairs, entries, graph, log)
+}
+
+/// Changes [`unset_option`] would make to `kvpairs`
+pub fn unset_changes(opt: &str, kvpairs: &[(String, String)], entries: &[ConfigEntry],
+                     graph: &Graph<&str, state::Complete>, log: &Logger)
+    -> Result<ChangeSet, Box<dyn error::Error>>
+{
+    log.writeln(Verbosity::Lvl1, &format!("Unsetting option {}", opt));
+    satisfy(opt, None, kvpairs, entries, graph, log)
+}
+
+/// Enable switch `opt` in `kvpairs`, changing as few other options as possible
+/// to satisfy dependencies
+pub fn enable_option(opt: &str, kvpairs: &mut Vec<(String, String)>, entries: &[ConfigEntry],
+                     graph: &Graph<&str, state::Complete>, log: &Logger)
+    -> Result<(), Box<dyn error::Error>>
+{
+    enable_changes(opt, kvpairs, entries, graph, log)?.apply(kvpairs);
     Ok(())
 }
 
-pub fn enable(opt: &str, path: &path::PathBuf, entries: &[ConfigEntry], log: &Logger)
+/// Disable switch `opt` in `kvpairs`, changing as few other options as possible
+/// to satisfy dependencies
+pub fn disable_option(opt: &str, kvpairs: &mut Vec<(String, String)>, entries: &[ConfigEntry],
+                      graph: &Graph<&str, state::Complete>, log: &Logger)
     -> Result<(), Box<dyn error::Error>>
 {
-    let mut kvpairs = parse::parse_config(path, None)?;
-    enable_dependencies(opt, &mut kvpairs, &entries, log)?;
-    log.writeln(Verbosity::Lvl1, &format!("Enabling switch {}", opt));
-    set_switch(opt, Switch::Yes, &mut kvpairs, &entries)?;
-    write_config(&kvpairs, path, log)
+    disable_changes(opt, kvpairs, entries, graph, log)?.apply(kvpairs);
+    Ok(())
 }
 
-pub fn disable(opt: &str, path: &path::PathBuf, entries: &[ConfigEntry], log: &Logger)
+/// Remove `opt` from `kvpairs`, disabling options that depend on it if its
+/// default leaves it disabled
+pub fn unset_option(opt: &str, kvpairs: &mut Vec<(String, String)>, entries: &[ConfigEntry],
+                    graph: &Graph<&str, state::Complete>, log: &Logger)
     -> Result<(), Box<dyn error::Error>>
 {
-    let mut kvpairs = parse::parse_config(path, None)?;
-    disable_dependent(opt, &mut kvpairs, &entries, log)?;
-    log.writeln(Verbosity::Lvl1, &format!("Disabling switch {}", opt));
-    set_switch(opt, Switch::No, &mut kvpairs, &entries)?;
+    unset_changes(opt, kvpairs, entries, graph, log)?.apply(kvpairs);
+    Ok(())
+}
+
+/// Review `changes` as requested, applying them to the config at `path` unless
+/// they are only printed or turned down
+fn commit(changes: &ChangeSet, mut kvpairs: Vec<(String, String)>, path: &path::Path, review: Review, log: &Logger)
+    -> Result<(), Box<dyn error::Error>>
+{
+    match review {
+        Review::DryRun => {
+            print!("{}", changes);
+            return Ok(());
+        },
+        Review::Json => {
+            println!("{}", changes.to_json()?);
+            return Ok(());
+        },
+        Review::Confirm => {
+            let stdin = io::stdin();
+            if !changes.confirm(&mut stdin.lock(), &mut io::stdout())? {
+                log.writeln(Verbosity::Lvl1, &"Leaving config unchanged");
+                return Ok(());
+            }
+        },
+        Review::Apply => ()
+    };
+    for change in changes.iter() {
+        log.writeln(Verbosity::Lvl1, change);
+    }
+    changes.apply(&mut kvpairs);
     write_config(&kvpairs, path, log)
 }
 
+pub fn enable(opt: &str, path: &path::PathBuf, review: Review, entries: &[ConfigEntry],
+              graph: &Graph<&str, state::Complete>, log: &Logger)
+    -> Result<(), Box<dyn error::Error>>
+{
+    let kvpairs = parse::parse_config(path, None)?;
+    let changes = enable_changes(opt, &kvpairs, entries, graph, log)?;
+    commit(&changes, kvpairs, path, review, log)
+}
+
+pub fn disable(opt: &str, path: &path::PathBuf, review: Review, entries: &[ConfigEntry],
+               graph: &Graph<&str, state::Complete>, log: &Logger)
+    -> Result<(), Box<dyn error::Error>>
+{
+    let kvpairs = parse::parse_config(path, None)?;
+    let changes = disable_changes(opt, &kvpairs, entries, graph, log)?;
+    commit(&changes, kvpairs, path, review, log)
+}
+
 fn is_integer(s: &str) -> bool {
-    return Regex::new(r"^\s*[0-9]+\s*$").unwrap()
-                                        .is_match(s);
+    Regex::new(r"^\s*[0-9]+\s*$").unwrap()
+                                 .is_match(s)
 }
 
-fn validate_value(opt: &str, value: &str, ent: &ConfigEntry) -> Result<(), Box<dyn error::Error>> {
+pub(crate) fn validate_value(opt: &str, value: &str, ent: &ConfigEntry) -> Result<(), Box<dyn error::Error>> {
     match ent.enttype {
         EntryType::Switch(_) => {
             if value != "y" && value != "n" {
@@ -151,7 +291,7 @@ fn validate_value(opt: &str, value: &str, ent: &ConfigEntry) -> Result<(), Box<d
         },
         EntryType::String(_) => (),
         EntryType::Int(_) => {
-            if !is_integer(&value) {
+            if !is_integer(value) {
                 return Err(format!("Invalid value \"{}\" for integer \"{}\"", value, opt).into());
             }
         }
@@ -166,8 +306,10 @@ fn validate_value(opt: &str, value: &str, ent: &ConfigEntry) -> Result<(), Box<d
     Ok(())
 }
 
-pub fn set(opt: &str, value: &str, path: &path::PathBuf, entries: &[ConfigEntry], log: &Logger)
-    -> Result<(), Box<dyn error::Error>>
+/// Changes [`set_option`] would make to `kvpairs`
+pub fn set_changes(opt: &str, value: &str, kvpairs: &[(String, String)], entries: &[ConfigEntry],
+                   graph: &Graph<&str, state::Complete>, log: &Logger)
+    -> Result<ChangeSet, Box<dyn error::Error>>
 {
     log.writeln(Verbosity::Lvl3, &format!("Looking up find option {}...", opt));
     let ent = match entries.iter().find(|e| e.name == opt) {
@@ -178,30 +320,80 @@ pub fn set(opt: &str, value: &str, path: &path::PathBuf, entries: &[ConfigEntry]
 
     let value = value.trim();
 
-    validate_value(opt, value, &ent)?;
+    validate_value(opt, value, ent)?;
     log.writeln(Verbosity::Lvl3, &format!("Value \"{}\" is valid for option \"{}\"", value, opt));
 
-    let mut kvpairs = parse::parse_config(path, None)?;
-    match ent.enttype {
-        EntryType::Switch(_) => {
-            if value == "n" {
-                disable_dependent(opt, &mut kvpairs, &entries, log)?;
-            }
-            else {
-                enable_dependencies(opt, &mut kvpairs, &entries, log)?;
-            }
-        },
-        _ => enable_dependencies(opt, &mut kvpairs, &entries, log)?
-    };
-
-    if let Some((_, v)) = kvpairs.iter_mut().find(|(k, _)| k == opt) {
+    if kvpairs.iter().any(|(k, _)| k == opt) {
         log.writeln(Verbosity::Lvl1, &format!("Setting value \"{}\" for existing option \"{}\"", value, opt));
-        *v = value.to_string();
     }
     else {
         log.writeln(Verbosity::Lvl1, &format!("Setting value \"{}\" for missing option \"{}\"", value, opt));
-        kvpairs.push((opt.to_string(), value.to_string()));
     }
+    satisfy(opt, Some(value), kvpairs, entries, graph, log)
+}
 
-    write_config(&kvpairs, path, log)
+/// Set `opt` to `value` in `kvpairs`
+///
+/// Other options are changed as little as possible to satisfy dependencies,
+/// which usually means disabling dependents when setting a switch to `n` and
+/// enabling dependencies otherwise.
+pub fn set_option(opt: &str, value: &str, kvpairs: &mut Vec<(String, String)>, entries: &[ConfigEntry],
+                  graph: &Graph<&str, state::Complete>, log: &Logger)
+    -> Result<(), Box<dyn error::Error>>
+{
+    set_changes(opt, value, kvpairs, entries, graph, log)?.apply(kvpairs);
+    Ok(())
+}
+
+pub fn set(opt: &str, value: &str, path: &path::PathBuf, review: Review, entries: &[ConfigEntry],
+           graph: &Graph<&str, state::Complete>, log: &Logger)
+    -> Result<(), Box<dyn error::Error>>
+{
+    let kvpairs = parse::parse_config(path, None)?;
+    let changes = set_changes(opt, value, &kvpairs, entries, graph, log)?;
+    commit(&changes, kvpairs, path, review, log)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::manipulate::*;
+    use crate::test_util::{entry, kvpairs};
+
+    fn entries() -> Vec<ConfigEntry> {
+        vec![
+            entry("CONFIG_BASE", &[], EntryType::Switch(Switch::Yes)),
+            entry("CONFIG_NET", &["CONFIG_BASE"], EntryType::Switch(Switch::Yes)),
+            entry("CONFIG_NET_PORT", &["CONFIG_NET"], EntryType::Int(80)),
+            entry("CONFIG_DEBUG", &[], EntryType::Switch(Switch::No))
+        ]
+    }
+
+    #[test]
+    fn keeps_options_enabled_by_default() -> Result<(), Box<dyn error::Error>> {
+        let entries = entries();
+        let graph = parse::spec_graph(&entries)?;
+        let log = Logger::new(0);
+        let mut config = kvpairs(&[("CONFIG_NET_PORT", "8080")]);
+        enable_option("CONFIG_DEBUG", &mut config, &entries, &graph, &log)?;
+        assert_eq!(config, kvpairs(&[("CONFIG_NET_PORT", "8080"), ("CONFIG_DEBUG", "y")]));
+
+        disable_option("CONFIG_BASE", &mut config, &entries, &graph, &log)?;
+        assert_eq!(config, kvpairs(&[("CONFIG_DEBUG", "y"), ("CONFIG_BASE", "n"), ("CONFIG_NET", "n")]));
+        Ok(())
+    }
+
+    #[test]
+    fn lists_dependencies_of_enabled_option() -> Result<(), Box<dyn error::Error>> {
+        let entries = entries();
+        let graph = parse::spec_graph(&entries)?;
+        let mut config = kvpairs(&[("CONFIG_DEBUG", "y")]);
+        set_option("CONFIG_NET_PORT", "8080", &mut config, &entries, &graph, &Logger::new(0))?;
+        assert_eq!(config, kvpairs(&[
+            ("CONFIG_DEBUG", "y"),
+            ("CONFIG_NET_PORT", "8080"),
+            ("CONFIG_BASE", "y"),
+            ("CONFIG_NET", "y")
+        ]));
+        Ok(())
+    }
 }
@@ -0,0 +1,543 @@
+use std::{error, path, process};
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use crate::generate::{self, Resolved};
+use crate::graph::{state, Graph};
+use crate::logger::{Logger, Verbosity};
+use crate::{manipulate, parse, ConfigEntry, EntryType};
+
+const RESET: &str = "\x1b[0m";
+const REVERSE: &str = "\x1b[7m";
+const DIM: &str = "\x1b[2m";
+const BOLD: &str = "\x1b[1m";
+const HELP_LINES: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Key {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Enter,
+    Backspace,
+    Interrupt,
+    /// Ctrl-L, redraw after querying the terminal size again
+    Redraw,
+    Char(char),
+    Other
+}
+
+/// Terminal in raw mode, restored when dropped
+#[derive(Debug)]
+struct Terminal {
+    /// Settings to restore, as reported by `stty -g`
+    saved: String,
+    stdin: io::Stdin,
+    stdout: io::Stdout
+}
+
+fn stty(args: &[&str]) -> Result<String, Box<dyn error::Error>> {
+    let out = process::Command::new("stty")
+                               .args(args)
+                               .stdin(process::Stdio::inherit())
+                               .output()?;
+    if !out.status.success() {
+        return Err("Standard input is not a terminal".into());
+    }
+    Ok(String::from_utf8(out.stdout)?.trim().to_string())
+}
+
+impl Terminal {
+    fn new() -> Result<Self, Box<dyn error::Error>> {
+        let saved = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+        let mut term = Terminal { saved, stdin: io::stdin(), stdout: io::stdout() };
+        // Alternate screen, hidden cursor
+        term.write("\x1b[?1049h\x1b[?25l")?;
+        Ok(term)
+    }
+
+    /// Terminal size as rows and columns, spawns `stty` so only query it when needed
+    fn size(&self) -> (usize, usize) {
+        let size = stty(&["size"]).unwrap_or_default();
+        let mut dims = size.split_whitespace().filter_map(|d| d.parse::<usize>().ok());
+        match (dims.next(), dims.next()) {
+            (Some(rows), Some(cols)) if rows > 0 && cols > 0 => (rows, cols),
+            _ => (24, 80)
+        }
+    }
+
+    fn write(&mut self, s: &str) -> Result<(), Box<dyn error::Error>> {
+        self.stdout.write_all(s.as_bytes())?;
+        self.stdout.flush()?;
+        Ok(())
+    }
+
+    fn read_byte(&mut self) -> Result<u8, Box<dyn error::Error>> {
+        let mut buf = [0u8; 1];
+        match self.stdin.read(&mut buf)? {
+            0 => Err("Unexpected end of input".into()),
+            _ => Ok(buf[0])
+        }
+    }
+
+    fn read_key(&mut self) -> Result<Key, Box<dyn error::Error>> {
+        let key = match self.read_byte()? {
+            b'\r' | b'\n' => Key::Enter,
+            0x7f | 0x08 => Key::Backspace,
+            0x03 => Key::Interrupt,
+            0x0c => Key::Redraw,
+            0x1b => match self.read_byte()? {
+                b'[' | b'O' => match self.read_byte()? {
+                    b'A' => Key::Up,
+                    b'B' => Key::Down,
+                    b'H' => Key::Home,
+                    b'F' => Key::End,
+                    b @ b'0'..=b'9' => {
+                        let mut code = vec![b];
+                        loop {
+                            match self.read_byte()? {
+                                b'~' => break,
+                                b => code.push(b)
+                            };
+                        }
+                        match code.as_slice() {
+                            b"5" => Key::PageUp,
+                            b"6" => Key::PageDown,
+                            b"1" | b"7" => Key::Home,
+                            b"4" | b"8" => Key::End,
+                            _ => Key::Other
+                        }
+                    },
+                    _ => Key::Other
+                },
+                _ => Key::Other
+            },
+            b if b.is_ascii() && !b.is_ascii_control() => Key::Char(b as char),
+            b if b >= 0xc0 => {
+                // Multi-byte utf-8 sequence
+                let len = if b >= 0xf0 { 4 } else if b >= 0xe0 { 3 } else { 2 };
+                let mut bytes = vec![b];
+                for _ in 1..len {
+                    bytes.push(self.read_byte()?);
+                }
+                match String::from_utf8(bytes).ok().and_then(|s| s.chars().next()) {
+                    Some(c) => Key::Char(c),
+                    None => Key::Other
+                }
+            },
+            _ => Key::Other
+        };
+        Ok(key)
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = self.write("\x1b[?25h\x1b[?1049l");
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+/// Result of handling a key press
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    Continue,
+    /// Prompt for a new value of the selected option
+    Edit,
+    /// Prompt for a search string
+    Search,
+    Save,
+    Quit
+}
+
+/// State of the menu, independent of the terminal
+#[derive(Debug)]
+struct Menu<'a> {
+    entries: &'a [ConfigEntry],
+    graph: &'a Graph<&'a str, state::Complete>,
+    kvpairs: Vec<(String, String)>,
+    /// Current config merged with defaults
+    resolved: Vec<Resolved<'a>>,
+    /// Indices into `entries` of options matching the current search
+    visible: Vec<usize>,
+    filter: String,
+    /// Index into `visible` of the selected option
+    selected: usize,
+    /// Index into `visible` of the first option on screen
+    offset: usize,
+    show_help: bool,
+    dirty: bool,
+    message: String,
+    /// Manipulations are silent, output would corrupt the screen
+    log: Logger
+}
+
+impl<'a> Menu<'a> {
+    fn new(kvpairs: Vec<(String, String)>, entries: &'a [ConfigEntry], graph: &'a Graph<&'a str, state::Complete>)
+        -> Result<Self, Box<dyn error::Error>>
+    {
+        let log = Logger::new(0);
+        let resolved = generate::resolve(&kvpairs, entries, graph, &log)?;
+        Ok(Menu {
+            entries,
+            graph,
+            kvpairs,
+            resolved,
+            visible: (0..entries.len()).collect(),
+            filter: String::new(),
+            selected: 0,
+            offset: 0,
+            show_help: true,
+            dirty: false,
+            message: String::new(),
+            log
+        })
+    }
+
+    fn current(&self) -> Option<&'a ConfigEntry> {
+        self.visible.get(self.selected).map(|&i| &self.entries[i])
+    }
+
+    /// Effective values of the options that have one, looked up while drawing a frame
+    fn values(&self) -> HashMap<&str, &str> {
+        self.resolved.iter()
+                     .filter_map(|r| r.value.as_deref().map(|v| (r.entry.name.as_str(), v)))
+                     .collect()
+    }
+
+    /// Whether all dependencies of the option are enabled
+    fn is_available(&self, ent: &ConfigEntry, values: &HashMap<&str, &str>) -> bool {
+        match self.graph.dependencies_of(&ent.name.as_str()) {
+            Ok(deps) => deps.iter().all(|d| values.get(d) == Some(&"y")),
+            Err(_) => false
+        }
+    }
+
+    fn search(&mut self, filter: &str) {
+        let needle = filter.to_lowercase();
+        self.filter = filter.to_string();
+        self.visible = self.entries.iter()
+                                   .enumerate()
+                                   .filter(|(_, e)| needle.is_empty() ||
+                                                    e.name.to_lowercase().contains(&needle) ||
+                                                    e.help.to_lowercase().contains(&needle))
+                                   .map(|(i, _)| i)
+                                   .collect();
+        self.selected = 0;
+        self.offset = 0;
+        self.message = match (self.filter.is_empty(), self.visible.len()) {
+            (true, _) => String::new(),
+            (false, n) => format!("{} options match \"{}\"", n, self.filter)
+        };
+    }
+
+    /// Apply a manipulation to the selected option using the semantics of [`manipulate`]
+    fn apply<F>(&mut self, f: F)
+    where
+        F: FnOnce(&str, &mut Vec<(String, String)>, &[ConfigEntry], &Graph<&str, state::Complete>, &Logger)
+            -> Result<(), Box<dyn error::Error>>
+    {
+        let ent = match self.current() {
+            Some(ent) => ent,
+            None => return
+        };
+        let mut kvpairs = self.kvpairs.clone();
+        let res = f(&ent.name, &mut kvpairs, self.entries, self.graph, &self.log)
+            .and_then(|_| generate::resolve(&kvpairs, self.entries, self.graph, &self.log));
+        match res {
+            Ok(resolved) => {
+                let changed = kvpairs != self.kvpairs;
+                self.dirty |= changed;
+                self.kvpairs = kvpairs;
+                self.resolved = resolved;
+                self.message = match changed {
+                    true => format!("Updated {}", ent.name),
+                    false => String::new()
+                };
+            },
+            Err(err) => self.message = err.to_string()
+        };
+    }
+
+    fn set(&mut self, value: &str) {
+        let value = value.to_string();
+        self.apply(|opt, kvpairs, entries, graph, log| manipulate::set_option(opt, &value, kvpairs, entries, graph, log));
+    }
+
+    fn toggle(&mut self) {
+        let ent = match self.current() {
+            Some(ent) => ent,
+            None => return
+        };
+        match self.values().get(ent.name.as_str()) == Some(&"y") {
+            true => self.apply(manipulate::disable_option),
+            false => self.apply(manipulate::enable_option)
+        };
+    }
+
+    fn scroll(&mut self, delta: isize, rows: usize) {
+        if self.visible.is_empty() {
+            return;
+        }
+        let last = self.visible.len() - 1;
+        self.selected = (self.selected as isize + delta).clamp(0, last as isize) as usize;
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        }
+        else if self.selected >= self.offset + rows {
+            self.offset = self.selected + 1 - rows;
+        }
+    }
+
+    fn handle(&mut self, key: &Key, rows: usize) -> Action {
+        let page = rows.max(1) as isize;
+        match key {
+            Key::Up | Key::Char('k') => self.scroll(-1, rows),
+            Key::Down | Key::Char('j') => self.scroll(1, rows),
+            Key::PageUp => self.scroll(-page, rows),
+            Key::PageDown => self.scroll(page, rows),
+            Key::Home | Key::Char('g') => self.scroll(isize::MIN / 2, rows),
+            Key::End | Key::Char('G') => self.scroll(isize::MAX / 2, rows),
+            Key::Char(' ') => match self.current() {
+                Some(ent) if ent.is_switch() => self.toggle(),
+                Some(_) => return Action::Edit,
+                None => ()
+            },
+            Key::Char('y') => self.apply(manipulate::enable_option),
+            Key::Char('n') => self.apply(manipulate::disable_option),
+            Key::Enter => match self.current() {
+                Some(ent) if ent.is_switch() => self.toggle(),
+                Some(_) => return Action::Edit,
+                None => ()
+            },
+            Key::Char('/') => return Action::Search,
+            Key::Char('?') | Key::Char('h') => self.show_help = !self.show_help,
+            Key::Char('s') => return Action::Save,
+            Key::Char('q') | Key::Interrupt => return Action::Quit,
+            _ => ()
+        };
+        Action::Continue
+    }
+
+    fn value_of(&self, ent: &ConfigEntry, values: &HashMap<&str, &str>) -> String {
+        match values.get(ent.name.as_str()) {
+            Some(value) => value.to_string(),
+            None => "-".to_string()
+        }
+    }
+
+    /// Number of list rows that fit on a screen of the given height
+    fn list_rows(&self, height: usize) -> usize {
+        let help = if self.show_help { HELP_LINES + 1 } else { 0 };
+        height.saturating_sub(3 + help).max(1)
+    }
+
+    fn draw(&self, height: usize, width: usize) -> String {
+        let rows = self.list_rows(height);
+        let fit = |s: &str| s.chars().take(width).collect::<String>();
+        let mut out = String::from("\x1b[H\x1b[2J");
+
+        let title = format!(" conftool menu{}{}", if self.dirty { " [modified]" } else { "" },
+                            if self.filter.is_empty() { String::new() } else { format!(" [search: {}]", self.filter) });
+        out.push_str(&format!("{}{}{}\r\n", BOLD, fit(&title), RESET));
+
+        let name_width = self.entries.iter().map(|e| e.name.len()).max().unwrap_or(0);
+        let values = self.values();
+        for row in 0..rows {
+            let idx = match self.visible.get(self.offset + row) {
+                Some(&idx) => idx,
+                None => {
+                    out.push_str("\r\n");
+                    continue;
+                }
+            };
+            let ent = &self.entries[idx];
+            let value = self.value_of(ent, &values);
+            let marker = match &ent.enttype {
+                EntryType::Switch(_) => format!("[{}]", if value == "y" { "*" } else { " " }),
+                _ => "   ".to_string()
+            };
+            let line = fit(&format!(" {} {:width$}  {}", marker, ent.name, value, width=name_width));
+            let style = match (self.offset + row == self.selected, self.is_available(ent, &values)) {
+                (true, _) => REVERSE,
+                (false, false) => DIM,
+                (false, true) => ""
+            };
+            out.push_str(&format!("{}{}{}\r\n", style, line, RESET));
+        }
+
+        if self.show_help {
+            out.push_str(&format!("{}{}{}\r\n", DIM, "-".repeat(width), RESET));
+            let lines: Vec<String> = match self.current() {
+                Some(ent) => ent.to_string().lines().map(|l| l.to_string()).collect(),
+                None => vec!["No matching options".to_string()]
+            };
+            for i in 0..HELP_LINES {
+                out.push_str(&format!("{}\r\n", fit(lines.get(i).map_or("", |l| l.as_str()))));
+            }
+        }
+
+        let keys = "space/enter: toggle or edit  y/n: enable/disable  /: search  ?: help  ^L: redraw  s: save  q: quit";
+        out.push_str(&format!("{}\r\n", fit(&self.message)));
+        out.push_str(&format!("{}{}{}", DIM, fit(keys), RESET));
+        out
+    }
+}
+
+/// Read a line of input on the bottom row, `None` if cancelled
+fn prompt(term: &mut Terminal, height: usize, question: &str, initial: &str) -> Result<Option<String>, Box<dyn error::Error>> {
+    let mut input = initial.to_string();
+    term.write("\x1b[?25h")?;
+    let res = loop {
+        term.write(&format!("\x1b[{};1H\x1b[2K{}{}", height, question, input))?;
+        match term.read_key()? {
+            Key::Enter => break Some(input),
+            Key::Interrupt => break None,
+            Key::Backspace => {
+                input.pop();
+            },
+            Key::Char(c) => input.push(c),
+            _ => ()
+        };
+    };
+    term.write("\x1b[?25l")?;
+    Ok(res)
+}
+
+/// Browse and edit the config at `path` in a full-screen terminal interface
+pub fn menu(path: &path::PathBuf, entries: &[ConfigEntry], graph: &Graph<&str, state::Complete>, log: &Logger)
+    -> Result<(), Box<dyn error::Error>>
+{
+    let kvpairs = if path.exists() {
+        parse::parse_config(path, None)?
+    }
+    else {
+        vec![]
+    };
+    let mut menu = Menu::new(kvpairs, entries, graph)?;
+    let mut term = Terminal::new()?;
+    let (mut height, mut width) = term.size();
+
+    loop {
+        let rows = menu.list_rows(height);
+        menu.scroll(0, rows);
+        term.write(&menu.draw(height, width))?;
+
+        let key = term.read_key()?;
+        if key == Key::Redraw {
+            (height, width) = term.size();
+            continue;
+        }
+        match menu.handle(&key, rows) {
+            Action::Continue => (),
+            Action::Edit => {
+                // Safe to unwrap, editing requires a selected option
+                let ent = menu.current().unwrap();
+                let current = menu.value_of(ent, &menu.values());
+                let question = match &ent.choices {
+                    Some(choices) => format!("{} ({}): ", ent.name, choices),
+                    None => format!("{}: ", ent.name)
+                };
+                if let Some(value) = prompt(&mut term, height, &question, current.trim_start_matches('-'))? {
+                    menu.set(&value);
+                }
+            },
+            Action::Search => {
+                let initial = menu.filter.clone();
+                if let Some(filter) = prompt(&mut term, height, "Search: ", &initial)? {
+                    menu.search(&filter);
+                }
+            },
+            Action::Save => {
+                manipulate::write_config(&menu.kvpairs, path, &menu.log)?;
+                menu.dirty = false;
+                menu.message = format!("Saved {}", path.display());
+            },
+            Action::Quit => {
+                if !menu.dirty {
+                    break;
+                }
+                match prompt(&mut term, height, "Save changes? [y/n/c]: ", "")?.as_deref().map(str::trim) {
+                    Some("y") | Some("Y") => {
+                        manipulate::write_config(&menu.kvpairs, path, &menu.log)?;
+                        break;
+                    },
+                    Some("n") | Some("N") => break,
+                    _ => menu.message = "Quit cancelled".to_string()
+                };
+            }
+        };
+    }
+
+    drop(term);
+    if menu.dirty {
+        log.writeln(Verbosity::Lvl1, &"Discarded unsaved changes");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::menu::*;
+    use crate::test_util::entry;
+    use crate::Switch;
+
+    fn entries() -> Vec<ConfigEntry> {
+        let entries = vec![
+            entry("CONFIG_BASE", &[], EntryType::Switch(Switch::No)),
+            entry("CONFIG_NET", &["CONFIG_BASE"], EntryType::Switch(Switch::No)),
+            entry("CONFIG_NET_PORT", &["CONFIG_NET"], EntryType::Int(80))
+        ];
+        entries.into_iter()
+               .map(|e| ConfigEntry { help: format!("Help for {}", e.name), ..e })
+               .collect()
+    }
+
+    #[test]
+    fn enabling_pulls_in_dependencies() -> Result<(), Box<dyn error::Error>> {
+        let entries = entries();
+        let graph = parse::spec_graph(&entries)?;
+        let mut menu = Menu::new(vec![], &entries, &graph)?;
+        assert!(!menu.is_available(&entries[1], &menu.values()));
+        menu.handle(&Key::Down, 10);
+        menu.handle(&Key::Char(' '), 10);
+        assert!(menu.dirty);
+        assert_eq!(menu.value_of(&entries[0], &menu.values()), "y");
+        assert_eq!(menu.value_of(&entries[1], &menu.values()), "y");
+        assert!(menu.is_available(&entries[2], &menu.values()));
+        Ok(())
+    }
+
+    #[test]
+    fn disabling_cascades_to_dependents() -> Result<(), Box<dyn error::Error>> {
+        let entries = entries();
+        let graph = parse::spec_graph(&entries)?;
+        let kvpairs = vec![("CONFIG_BASE".to_string(), "y".to_string()),
+                           ("CONFIG_NET".to_string(), "y".to_string()),
+                           ("CONFIG_NET_PORT".to_string(), "80".to_string())];
+        let mut menu = Menu::new(kvpairs, &entries, &graph)?;
+        menu.handle(&Key::Char('n'), 10);
+        assert_eq!(menu.value_of(&entries[1], &menu.values()), "n");
+        assert_eq!(menu.value_of(&entries[2], &menu.values()), "-");
+        Ok(())
+    }
+
+    #[test]
+    fn search_filters_and_edit_validates() -> Result<(), Box<dyn error::Error>> {
+        let entries = entries();
+        let graph = parse::spec_graph(&entries)?;
+        let mut menu = Menu::new(vec![], &entries, &graph)?;
+        menu.search("port");
+        assert_eq!(menu.visible, vec![2]);
+        assert_eq!(menu.handle(&Key::Enter, 10), Action::Edit);
+        menu.set("abc");
+        assert!(!menu.dirty);
+        menu.set("8080");
+        assert_eq!(menu.value_of(&entries[2], &menu.values()), "8080");
+        assert_eq!(menu.value_of(&entries[1], &menu.values()), "y");
+        Ok(())
+    }
+}
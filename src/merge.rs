@@ -0,0 +1,167 @@
+use std::{collections, error, fmt, path};
+use crate::generate::{self, Origin};
+use crate::graph::{state, Graph};
+use crate::logger::{Logger, Verbosity};
+use crate::{manipulate, parse, upgrade, validate, ConfigEntry};
+
+/// Value from one fragment replaced by a later one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Override {
+    pub option: String,
+    pub old: String,
+    /// Fragment the replaced value came from
+    pub old_source: String,
+    pub new: String,
+    /// Fragment the new value came from
+    pub new_source: String
+}
+
+impl fmt::Display for Override {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} = {} overrides {} from {}",
+               self.new_source, self.option, self.new, self.old, self.old_source)
+    }
+}
+
+/// Merge named fragments, values from later fragments taking precedence
+///
+/// Options keep the position at which they first appeared.
+pub fn merge_fragments(fragments: &[(String, Vec<(String, String)>)], log: &Logger)
+    -> (Vec<(String, String)>, Vec<Override>)
+{
+    let mut merged: Vec<(String, String)> = vec![];
+    let mut sources: Vec<&str> = vec![];
+    let mut overrides = vec![];
+
+    for (source, kvpairs) in fragments {
+        log.writeln(Verbosity::Lvl1, &format!("Merging {}", source));
+        for (opt, val) in kvpairs {
+            match merged.iter().position(|(k, _)| k == opt) {
+                Some(i) => {
+                    if merged[i].1 != *val {
+                        overrides.push(Override {
+                            option: opt.clone(),
+                            old: merged[i].1.clone(),
+                            old_source: sources[i].to_string(),
+                            new: val.clone(),
+                            new_source: source.clone()
+                        });
+                    }
+                    log.writeln(Verbosity::Lvl2, &format!("Setting \"{}\" to \"{}\" from {}", opt, val, source));
+                    merged[i].1 = val.clone();
+                    sources[i] = source;
+                },
+                None => {
+                    log.writeln(Verbosity::Lvl2, &format!("Adding \"{}\" = \"{}\" from {}", opt, val, source));
+                    merged.push((opt.clone(), val.clone()));
+                    sources.push(source);
+                }
+            };
+        }
+    }
+    (merged, overrides)
+}
+
+/// List the dependencies of configured options that are only enabled by default
+///
+/// Validation expects every dependency of an enabled option to be listed, and
+/// [`upgrade::enforce_dependencies`] would take an unlisted one as disabled.
+fn list_default_dependencies(kvpairs: &mut Vec<(String, String)>, entries: &[ConfigEntry],
+                             graph: &Graph<&str, state::Complete>, log: &Logger)
+    -> Result<(), Box<dyn error::Error>>
+{
+    let resolved = generate::resolve(kvpairs, entries, graph, log)?;
+    let by_default: collections::HashSet<&str> = resolved.iter()
+                                                         .filter(|r| r.origin == Origin::Default)
+                                                         .filter(|r| r.value.as_deref() == Some("y"))
+                                                         .map(|r| r.entry.name.as_str())
+                                                         .collect();
+    let mut missing = collections::HashSet::new();
+    for (opt, val) in kvpairs.iter() {
+        match entries.iter().find(|e| e.name == *opt) {
+            Some(ent) if !ent.is_switch() || val != "n" => (),
+            _ => continue
+        };
+        missing.extend(graph.dependencies_of(&opt.as_str())?
+                            .into_iter()
+                            .filter_map(|d| by_default.get(d).copied()));
+    }
+    for ent in entries.iter().filter(|e| missing.contains(e.name.as_str())) {
+        log.writeln(Verbosity::Lvl2, &format!("Listing dependency \"{}\" enabled by default", ent.name));
+        kvpairs.push((ent.name.clone(), "y".to_string()));
+    }
+    Ok(())
+}
+
+/// Merge config fragments into `output`, in the style of the kernel's `merge_config.sh`
+///
+/// Warnings are printed for overridden values and for options that had to be changed
+/// to satisfy dependencies. The result is validated before being written.
+pub fn merge(fragments: &[path::PathBuf], output: &path::Path, entries: &[ConfigEntry],
+             graph: &Graph<&str, state::Complete>, log: &Logger)
+    -> Result<(), Box<dyn error::Error>>
+{
+    let mut parsed = Vec::with_capacity(fragments.len());
+    for fragment in fragments {
+        parsed.push((fragment.display().to_string(), parse::parse_config(fragment, None)?));
+    }
+
+    let (mut merged, overrides) = merge_fragments(&parsed, log);
+    for o in &overrides {
+        eprintln!("Warning: {}", o);
+    }
+
+    list_default_dependencies(&mut merged, entries, graph, log)?;
+    for change in upgrade::enforce_dependencies(&mut merged, entries, graph, log)? {
+        eprintln!("Warning: {}", change);
+    }
+
+    validate::validate_kvpairs(&merged, entries, graph, log)?;
+    manipulate::write_config(&merged, output, log)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::merge::*;
+    use crate::test_util::entry;
+    use crate::{EntryType, Switch};
+    use std::fs;
+
+    fn fragment(name: &str, pairs: &[(&str, &str)]) -> (String, Vec<(String, String)>) {
+        (name.to_string(), pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect())
+    }
+
+    #[test]
+    fn later_fragments_override_earlier_ones() {
+        let fragments = vec![
+            fragment("base", &[("CONFIG_A", "y"), ("CONFIG_B", "1")]),
+            fragment("soc", &[("CONFIG_B", "2"), ("CONFIG_C", "n")]),
+            fragment("feature", &[("CONFIG_A", "y"), ("CONFIG_B", "3")])
+        ];
+        let (merged, overrides) = merge_fragments(&fragments, &Logger::new(0));
+        assert_eq!(merged, fragment("", &[("CONFIG_A", "y"), ("CONFIG_B", "3"), ("CONFIG_C", "n")]).1);
+        assert_eq!(overrides.len(), 2);
+        assert_eq!(overrides[1].to_string(), "feature: CONFIG_B = 3 overrides 2 from soc");
+    }
+
+    #[test]
+    fn dependencies_enabled_by_default_keep_options() -> Result<(), Box<dyn error::Error>> {
+        let entries = vec![
+            entry("CONFIG_NET", &[], EntryType::Switch(Switch::Yes)),
+            entry("CONFIG_PORT", &["CONFIG_NET"], EntryType::Int(80)),
+            entry("CONFIG_DEBUG", &[], EntryType::Switch(Switch::No))
+        ];
+        let graph = parse::spec_graph(&entries)?;
+        let dir = std::env::temp_dir().join(format!("conftool-merge-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let (base, feature, output) = (dir.join("base"), dir.join("feature"), dir.join("config"));
+        fs::write(&base, "CONFIG_DEBUG = y\n")?;
+        fs::write(&feature, "CONFIG_PORT = 8080\n")?;
+
+        merge(&[base, feature], &output, &entries, &graph, &Logger::new(0))?;
+        let merged = fs::read_to_string(&output)?;
+        fs::remove_dir_all(&dir)?;
+        assert_eq!(merged, "CONFIG_DEBUG = y\nCONFIG_PORT  = 8080\nCONFIG_NET   = y\n");
+        Ok(())
+    }
+}
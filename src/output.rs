@@ -0,0 +1,191 @@
+use std::{error, fs, io, path};
+use crate::logger::{Logger, Verbosity};
+
+/// How generated files are handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WriteMode {
+    /// Replace files whose contents changed
+    #[default]
+    Write,
+    /// Only report files whose contents are out of date
+    Check
+}
+
+/// Result of writing a single file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// File already had the expected contents
+    Unchanged,
+    /// File was created or replaced
+    Written,
+    /// File was removed as it is no longer generated
+    Removed,
+    /// File is out of date, only produced in [`WriteMode::Check`]
+    Stale
+}
+
+#[derive(Debug, PartialEq)]
+enum DiffLine<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str)
+}
+
+/// Line-based diff using the longest common subsequence
+fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<DiffLine<'a>> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = match old[i] == new[j] {
+                true => lcs[i + 1][j + 1] + 1,
+                false => lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0usize, 0usize);
+    let mut diff = Vec::with_capacity(old.len().max(new.len()));
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            diff.push(DiffLine::Same(old[i]));
+            i += 1;
+            j += 1;
+        }
+        else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            diff.push(DiffLine::Removed(old[i]));
+            i += 1;
+        }
+        else {
+            diff.push(DiffLine::Added(new[j]));
+            j += 1;
+        }
+    }
+    diff
+}
+
+/// Print the changes needed to turn `old` into `new`
+fn print_diff(path: &path::Path, old: &str, new: &str) {
+    println!("--- {}", path.display());
+    println!("+++ {} (expected)", path.display());
+    for line in diff_lines(old, new) {
+        match line {
+            DiffLine::Same(_) => (),
+            DiffLine::Removed(line) => println!("-{}", line),
+            DiffLine::Added(line) => println!("+{}", line)
+        };
+    }
+}
+
+/// Write `contents` to `path` unless the file already contains exactly that
+///
+/// Leaving up-to-date files untouched preserves their modification time so that
+/// build systems do not rebuild needlessly. In [`WriteMode::Check`], nothing is
+/// written and a diff is printed for files that are out of date.
+pub fn write(path: &path::Path, contents: &str, mode: WriteMode, log: &Logger)
+    -> Result<Outcome, Box<dyn error::Error>>
+{
+    let current = match fs::read_to_string(path) {
+        Ok(current) => Some(current),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+        Err(err) => return Err(err.into())
+    };
+
+    if current.as_deref() == Some(contents) {
+        log.writeln(Verbosity::Lvl2, &format!("{} is up to date", path.display()));
+        return Ok(Outcome::Unchanged);
+    }
+
+    match mode {
+        WriteMode::Write => {
+            log.writeln(Verbosity::Lvl2, &format!("Writing {}", path.display()));
+            fs::write(path, contents)?;
+            Ok(Outcome::Written)
+        },
+        WriteMode::Check => {
+            match &current {
+                Some(current) => print_diff(path, current, contents),
+                None => println!("{} does not exist", path.display())
+            };
+            Ok(Outcome::Stale)
+        }
+    }
+}
+
+/// Remove `path`, which is no longer generated
+///
+/// In [`WriteMode::Check`], nothing is removed and the file is reported as out of date.
+pub fn remove(path: &path::Path, mode: WriteMode, log: &Logger) -> Result<Outcome, Box<dyn error::Error>> {
+    match mode {
+        WriteMode::Write => {
+            log.writeln(Verbosity::Lvl2, &format!("Removing {}", path.display()));
+            fs::remove_file(path)?;
+            Ok(Outcome::Removed)
+        },
+        WriteMode::Check => {
+            println!("{} is no longer generated", path.display());
+            Ok(Outcome::Stale)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::output::*;
+
+    #[test]
+    fn diff_marks_changed_lines() {
+        let diff = diff_lines("a\nb\nc\n", "a\nx\nc\nd\n");
+        assert_eq!(diff, vec![
+            DiffLine::Same("a"),
+            DiffLine::Removed("b"),
+            DiffLine::Added("x"),
+            DiffLine::Same("c"),
+            DiffLine::Added("d")
+        ]);
+    }
+
+    #[test]
+    fn diff_of_identical_input_has_no_changes() {
+        let diff = diff_lines("a\nb\n", "a\nb\n");
+        assert!(diff.iter().all(|l| matches!(l, DiffLine::Same(_))));
+    }
+
+    fn temp_path(name: &str) -> path::PathBuf {
+        std::env::temp_dir().join(format!("conftool-output-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn unchanged_file_is_left_untouched() -> Result<(), Box<dyn error::Error>> {
+        let path = temp_path("unchanged");
+        fs::write(&path, "a\n")?;
+        let past = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        fs::File::options().write(true).open(&path)?.set_modified(past)?;
+
+        let log = Logger::new(0);
+        assert_eq!(write(&path, "a\n", WriteMode::Write, &log)?, Outcome::Unchanged);
+        assert_eq!(fs::metadata(&path)?.modified()?, past);
+        assert_eq!(write(&path, "b\n", WriteMode::Write, &log)?, Outcome::Written);
+        assert_eq!(fs::read_to_string(&path)?, "b\n");
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn check_reports_drift_without_writing() -> Result<(), Box<dyn error::Error>> {
+        let path = temp_path("check");
+        fs::write(&path, "a\n")?;
+
+        let log = Logger::new(0);
+        assert_eq!(write(&path, "a\n", WriteMode::Check, &log)?, Outcome::Unchanged);
+        assert_eq!(write(&path, "b\n", WriteMode::Check, &log)?, Outcome::Stale);
+        assert_eq!(fs::read_to_string(&path)?, "a\n");
+        fs::remove_file(&path)?;
+
+        assert_eq!(write(&path, "b\n", WriteMode::Check, &log)?, Outcome::Stale);
+        assert!(!path.exists());
+        Ok(())
+    }
+}
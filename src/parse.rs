@@ -4,6 +4,7 @@ use std::error;
 use std::fs;
 use std::path;
 use crate::{display_vec, ConfigEntry, EntryType, Switch};
+use crate::graph::{state, Graph};
 
 #[derive(Debug, serde::Deserialize)]
 struct ParseEntry {
@@ -54,11 +55,8 @@ pub fn parse_spec(path: &path::PathBuf) -> Result<Vec<ConfigEntry>, Box<dyn erro
         let entry = ConfigEntry {
             name: ent.name,
             depends: display_vec::DisplayVec::from(ent.depends),
-            enttype: enttype,
-            choices: match  ent.choices {
-                Some(choices) => Some(display_vec::DisplayVec::from(choices)),
-                None => None
-            },
+            enttype,
+            choices: ent.choices.map(display_vec::DisplayVec::from),
             help: ent.help
         };
         entries.push(entry)
@@ -67,6 +65,18 @@ pub fn parse_spec(path: &path::PathBuf) -> Result<Vec<ConfigEntry>, Box<dyn erro
     Ok(entries)
 }
 
+/// Build the dependency graph of a parsed specification
+///
+/// Refuses specifications with duplicate options, missing dependencies or cycles.
+pub fn spec_graph(entries: &[ConfigEntry]) -> Result<Graph<&str, state::Complete>, Box<dyn error::Error>> {
+    let mut graph = Graph::<&str, state::Incomplete>::new();
+    for ent in entries {
+        let depends: Vec<&str> = ent.depends.iter().map(|d| d.as_str()).collect();
+        graph.insert(&ent.name, &depends)?;
+    }
+    graph.into_complete()
+}
+
 pub fn parse_config(path: &path::PathBuf, lines: Option<Vec<String>>) -> Result<Vec<(String, String)>, Box<dyn error::Error>> {
     let lines = match lines {
         Some(lines) => lines,
@@ -0,0 +1,489 @@
+use std::{collections, error, fmt};
+use crate::graph::{state, EdgeKind, Graph};
+use crate::logger::{Logger, Verbosity};
+
+/// Number of search nodes explored before settling for the best assignment found
+const SEARCH_BUDGET: usize = 100_000;
+
+/// Constraint on whether options are enabled
+///
+/// Switches are enabled when set to `y`, other options when present in the config.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Constraint {
+    /// `option` may only be enabled if `dependency` is enabled
+    Requires {
+        option: String,
+        dependency: String
+    },
+    /// `option` may only be enabled if at least one of `alternatives` is enabled
+    AnyOf {
+        option: String,
+        alternatives: Vec<String>
+    },
+    /// `a` and `b` cannot both be enabled
+    Conflicts {
+        a: String,
+        b: String
+    },
+    /// `option` must be enabled or disabled
+    Fixed {
+        option: String,
+        enabled: bool
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Constraint::Requires { option, dependency } => write!(f, "{} requires {}", option, dependency),
+            Constraint::AnyOf { option, alternatives } =>
+                write!(f, "{} requires one of {}", option, alternatives.join(", ")),
+            Constraint::Conflicts { a, b } => write!(f, "{} conflicts with {}", a, b),
+            Constraint::Fixed { option, enabled: true } => write!(f, "{} must be enabled", option),
+            Constraint::Fixed { option, enabled: false } => write!(f, "{} must be disabled", option)
+        }
+    }
+}
+
+/// Set of constraints that cannot be satisfied together, and from which no
+/// constraint can be removed without making the rest satisfiable
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub constraints: Vec<Constraint>
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Conflicting constraints:")?;
+        for c in &self.constraints {
+            write!(f, "\n  {}", c)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for Conflict {}
+
+/// Literal of a clause, a variable and the value satisfying it
+type Lit = (usize, bool);
+
+/// Boolean model of which options are enabled
+#[derive(Debug, Clone)]
+pub struct Model {
+    /// Option names, in the order variables are decided during search
+    options: Vec<String>,
+    index: collections::HashMap<String, usize>,
+    constraints: Vec<Constraint>
+}
+
+impl Model {
+    /// Model with one variable per option in `options` and a constraint per edge of `graph` between them
+    ///
+    /// Depends and selects edges become a [`Constraint::Requires`], conflicts edges a
+    /// [`Constraint::Conflicts`]. Edges to options outside the model are left to the
+    /// caller, usually as [`Constraint::Fixed`] requests.
+    ///
+    /// Specifications cannot express alternative dependencies yet, so
+    /// [`Constraint::AnyOf`] is only ever added by callers.
+    pub fn from_graph(options: &[&str], graph: &Graph<&str, state::Complete>) -> Result<Self, Box<dyn error::Error>> {
+        let mut model = Model {
+            options: options.iter().map(|o| o.to_string()).collect(),
+            index: options.iter().enumerate().map(|(i, o)| (o.to_string(), i)).collect(),
+            constraints: vec![]
+        };
+        for opt in options {
+            for dep in graph.direct_dependencies_of_kind(opt, &[EdgeKind::Depends, EdgeKind::Selects])? {
+                if model.index.contains_key(dep) {
+                    model.add(Constraint::Requires { option: opt.to_string(), dependency: dep.to_string() })?;
+                }
+            }
+            for other in graph.direct_dependencies_of_kind(opt, &[EdgeKind::Conflicts])? {
+                if model.index.contains_key(other) {
+                    model.add(Constraint::Conflicts { a: opt.to_string(), b: other.to_string() })?;
+                }
+            }
+        }
+        Ok(model)
+    }
+
+    /// Add a constraint, failing if it refers to an unknown option
+    pub fn add(&mut self, constraint: Constraint) -> Result<(), Box<dyn error::Error>> {
+        self.clause(&constraint)?;
+        self.constraints.push(constraint);
+        Ok(())
+    }
+
+    pub fn options(&self) -> &[String] {
+        &self.options
+    }
+
+    pub fn constraints(&self) -> &[Constraint] {
+        &self.constraints
+    }
+
+    fn var(&self, option: &str) -> Result<usize, Box<dyn error::Error>> {
+        match self.index.get(option) {
+            Some(i) => Ok(*i),
+            None => Err(format!("Invalid config option \"{}\"", option).into())
+        }
+    }
+
+    fn clause(&self, constraint: &Constraint) -> Result<Vec<Lit>, Box<dyn error::Error>> {
+        Ok(match constraint {
+            Constraint::Requires { option, dependency } => vec![(self.var(option)?, false), (self.var(dependency)?, true)],
+            Constraint::AnyOf { option, alternatives } => {
+                let mut clause = vec![(self.var(option)?, false)];
+                for alt in alternatives {
+                    clause.push((self.var(alt)?, true));
+                }
+                clause
+            },
+            Constraint::Conflicts { a, b } => vec![(self.var(a)?, false), (self.var(b)?, false)],
+            Constraint::Fixed { option, enabled } => vec![(self.var(option)?, *enabled)]
+        })
+    }
+
+    fn clauses<'c, I>(&self, constraints: I) -> Result<Vec<Vec<Lit>>, Box<dyn error::Error>>
+    where
+        I: IntoIterator<Item = &'c Constraint>
+    {
+        constraints.into_iter().map(|c| self.clause(c)).collect()
+    }
+
+    /// Find an assignment satisfying the model and `requests` that differs from
+    /// `current` in as few options as possible
+    ///
+    /// The search is exhaustive up to a fixed budget, after which the closest
+    /// assignment found so far is returned. If no assignment exists, the error
+    /// is a minimal [`Conflict`].
+    pub fn solve(&self, current: &[bool], requests: &[Constraint], log: &Logger)
+        -> Result<Vec<bool>, Box<dyn error::Error>>
+    {
+        if current.len() != self.options.len() {
+            return Err(format!("Expected {} values, got {}", self.options.len(), current.len()).into());
+        }
+        let clauses = self.clauses(self.constraints.iter().chain(requests))?;
+
+        let mut search = Search::new(&clauses, current);
+        match search.run(SEARCH_BUDGET) {
+            Some(solution) => {
+                if !search.exhaustive {
+                    log.writeln(Verbosity::Lvl1, &"Search budget exhausted, solution may not be the closest");
+                }
+                log.writeln(Verbosity::Lvl2, &format!("Found solution changing {} options", search.best_cost));
+                Ok(solution)
+            },
+            None => Err(Box::new(self.minimal_conflict(requests, log)?))
+        }
+    }
+
+    fn satisfiable(&self, constraints: &[&Constraint]) -> Result<bool, Box<dyn error::Error>> {
+        let clauses = self.clauses(constraints.iter().copied())?;
+        let current = vec![false; self.options.len()];
+        Ok(Search::new(&clauses, &current).run(0).is_some())
+    }
+
+    /// Shrink the unsatisfiable model and `requests` to a minimal conflicting set
+    fn minimal_conflict(&self, requests: &[Constraint], log: &Logger) -> Result<Conflict, Box<dyn error::Error>> {
+        // Without requests everything can be disabled, so every conflict involves
+        // a request and only constraints connected to one need to be considered
+        let mut reached: collections::HashSet<usize> = collections::HashSet::new();
+        for r in requests {
+            reached.extend(self.clause(r)?.iter().map(|(v, _)| *v));
+        }
+        let mut candidates: Vec<&Constraint> = requests.iter().collect();
+        let mut rest: Vec<&Constraint> = self.constraints.iter().collect();
+        loop {
+            let before = candidates.len();
+            let mut i = 0;
+            while i < rest.len() {
+                let vars: Vec<usize> = self.clause(rest[i])?.iter().map(|(v, _)| *v).collect();
+                if vars.iter().any(|v| reached.contains(v)) {
+                    reached.extend(vars);
+                    candidates.push(rest.remove(i));
+                }
+                else {
+                    i += 1;
+                }
+            }
+            if candidates.len() == before {
+                break;
+            }
+        }
+
+        log.writeln(Verbosity::Lvl1, &format!("Minimizing conflict among {} constraints", candidates.len()));
+        let mut i = 0;
+        while i < candidates.len() {
+            let without: Vec<&Constraint> = candidates.iter()
+                                                      .enumerate()
+                                                      .filter(|(j, _)| *j != i)
+                                                      .map(|(_, c)| *c)
+                                                      .collect();
+            if self.satisfiable(&without)? {
+                i += 1;
+            }
+            else {
+                log.writeln(Verbosity::Lvl3, &format!("Dropping \"{}\" from conflict", candidates[i]));
+                candidates = without;
+            }
+        }
+        Ok(Conflict { constraints: candidates.into_iter().cloned().collect() })
+    }
+}
+
+/// Decision made during search, undone on backtracking
+#[derive(Debug)]
+struct Decision {
+    var: usize,
+    /// Length of the trail before the decision
+    trail: usize,
+    /// Whether the value differing from the current one was tried as well
+    flipped: bool
+}
+
+/// Branch-and-bound search with unit propagation, minimizing changes to `current`
+#[derive(Debug)]
+struct Search<'a> {
+    clauses: &'a [Vec<Lit>],
+    occurs: Vec<Vec<usize>>,
+    current: &'a [bool],
+    assign: Vec<Option<bool>>,
+    trail: Vec<usize>,
+    cost: usize,
+    best: Option<Vec<bool>>,
+    best_cost: usize,
+    exhaustive: bool
+}
+
+impl<'a> Search<'a> {
+    fn new(clauses: &'a [Vec<Lit>], current: &'a [bool]) -> Self {
+        let mut occurs = vec![vec![]; current.len()];
+        for (i, clause) in clauses.iter().enumerate() {
+            for (var, _) in clause {
+                occurs[*var].push(i);
+            }
+        }
+        Search {
+            clauses,
+            occurs,
+            current,
+            assign: vec![None; current.len()],
+            trail: vec![],
+            cost: 0,
+            best: None,
+            best_cost: usize::MAX,
+            exhaustive: true
+        }
+    }
+
+    fn set(&mut self, var: usize, value: bool) {
+        self.assign[var] = Some(value);
+        self.trail.push(var);
+        if value != self.current[var] {
+            self.cost += 1;
+        }
+    }
+
+    fn undo(&mut self, len: usize) {
+        while self.trail.len() > len {
+            let var = self.trail.pop().unwrap();
+            if self.assign[var] != Some(self.current[var]) {
+                self.cost -= 1;
+            }
+            self.assign[var] = None;
+        }
+    }
+
+    /// Assign values forced by clauses, starting at trail position `head`,
+    /// returning false on a violated clause
+    fn propagate(&mut self, mut head: usize) -> bool {
+        while head < self.trail.len() {
+            let var = self.trail[head];
+            head += 1;
+            for c in 0..self.occurs[var].len() {
+                let clause = &self.clauses[self.occurs[var][c]];
+                let mut unassigned = None;
+                let mut count = 0;
+                let mut satisfied = false;
+                for &(v, value) in clause {
+                    match self.assign[v] {
+                        Some(a) if a == value => {
+                            satisfied = true;
+                            break;
+                        },
+                        Some(_) => (),
+                        None => {
+                            count += 1;
+                            unassigned = Some((v, value));
+                        }
+                    };
+                }
+                if satisfied {
+                    continue;
+                }
+                match (count, unassigned) {
+                    (0, _) => return false,
+                    (1, Some((v, value))) => self.set(v, value),
+                    _ => ()
+                };
+            }
+        }
+        true
+    }
+
+    /// Run the search, giving up on improving the first solution after `budget` nodes
+    fn run(&mut self, budget: usize) -> Option<Vec<bool>> {
+        // Unit clauses are not triggered by any assignment, apply them up front
+        for clause in self.clauses {
+            if let [(var, value)] = clause[..] {
+                match self.assign[var] {
+                    Some(a) if a != value => return None,
+                    Some(_) => (),
+                    None => self.set(var, value)
+                };
+            }
+        }
+        let mut ok = self.propagate(0);
+        let mut stack: Vec<Decision> = vec![];
+        let mut nodes = 0;
+
+        loop {
+            if ok && self.cost < self.best_cost {
+                // Variables before the latest decision are all assigned
+                let from = stack.last().map_or(0, |d| d.var + 1);
+                match self.assign[from..].iter().position(|a| a.is_none()).map(|i| from + i) {
+                    Some(var) => {
+                        nodes += 1;
+                        stack.push(Decision { var, trail: self.trail.len(), flipped: false });
+                        let head = self.trail.len();
+                        self.set(var, self.current[var]);
+                        ok = self.propagate(head);
+                        continue;
+                    },
+                    None => {
+                        self.best_cost = self.cost;
+                        self.best = Some(self.assign.iter().map(|a| a.unwrap()).collect());
+                        if self.best_cost == 0 {
+                            break;
+                        }
+                    }
+                }
+            }
+            if self.best.is_some() && nodes > budget {
+                self.exhaustive = false;
+                break;
+            }
+
+            // Backtrack to the most recent decision with an untried value
+            let decision = loop {
+                match stack.pop() {
+                    Some(d) if !d.flipped => break Some(d),
+                    Some(_) => (),
+                    None => break None
+                };
+            };
+            let mut decision = match decision {
+                Some(decision) => decision,
+                None => break
+            };
+            self.undo(decision.trail);
+            decision.flipped = true;
+            let (var, head) = (decision.var, self.trail.len());
+            stack.push(decision);
+            self.set(var, !self.current[var]);
+            ok = self.propagate(head);
+        }
+        self.best.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::solver::*;
+
+    fn model(options: &[&str], constraints: Vec<Constraint>) -> Model {
+        let mut model = Model {
+            options: options.iter().map(|o| o.to_string()).collect(),
+            index: options.iter().enumerate().map(|(i, o)| (o.to_string(), i)).collect(),
+            constraints: vec![]
+        };
+        for c in constraints {
+            model.add(c).unwrap();
+        }
+        model
+    }
+
+    fn requires(option: &str, dependency: &str) -> Constraint {
+        Constraint::Requires { option: option.to_string(), dependency: dependency.to_string() }
+    }
+
+    fn any_of(option: &str, alternatives: &[&str]) -> Constraint {
+        Constraint::AnyOf { option: option.to_string(), alternatives: alternatives.iter().map(|s| s.to_string()).collect() }
+    }
+
+    fn fixed(option: &str, enabled: bool) -> Constraint {
+        Constraint::Fixed { option: option.to_string(), enabled }
+    }
+
+    #[test]
+    fn finds_closest_assignment() -> Result<(), Box<dyn error::Error>> {
+        let model = model(&["A", "B", "C", "D"], vec![requires("C", "B"), requires("B", "A")]);
+        let solution = model.solve(&[false, false, false, true], &[fixed("C", true)], &Logger::new(0))?;
+        assert_eq!(solution, vec![true, true, true, true]);
+        Ok(())
+    }
+
+    #[test]
+    fn disabling_propagates_to_dependents() -> Result<(), Box<dyn error::Error>> {
+        let model = model(&["A", "B", "C"], vec![requires("B", "A"), requires("C", "B")]);
+        let solution = model.solve(&[true, true, true], &[fixed("A", false)], &Logger::new(0))?;
+        assert_eq!(solution, vec![false, false, false]);
+        Ok(())
+    }
+
+    #[test]
+    fn reports_minimal_conflict() {
+        let model = model(&["A", "B", "C", "D"], vec![
+            requires("C", "A"),
+            requires("D", "C"),
+            Constraint::Conflicts { a: "A".to_string(), b: "B".to_string() },
+            requires("D", "B")
+        ]);
+        // C is satisfiable on its own, D drags in both sides of the conflict
+        let err = model.solve(&[false; 4], &[fixed("C", true), fixed("D", true)], &Logger::new(0)).unwrap_err();
+        let conflict = err.downcast_ref::<Conflict>().unwrap();
+        assert_eq!(conflict.constraints, vec![
+            fixed("D", true),
+            requires("C", "A"),
+            requires("D", "C"),
+            Constraint::Conflicts { a: "A".to_string(), b: "B".to_string() },
+            requires("D", "B")
+        ]);
+    }
+
+    #[test]
+    fn prefers_enabled_alternative() -> Result<(), Box<dyn error::Error>> {
+        let model = model(&["A", "B", "C"], vec![any_of("C", &["A", "B"])]);
+        let solution = model.solve(&[false, true, false], &[fixed("C", true)], &Logger::new(0))?;
+        assert_eq!(solution, vec![false, true, true]);
+
+        // With neither enabled, one alternative is enough
+        let solution = model.solve(&[false, false, false], &[fixed("C", true)], &Logger::new(0))?;
+        assert_eq!(solution.iter().filter(|&&v| v).count(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn conflict_covers_every_alternative() {
+        let model = model(&["A", "B", "C"], vec![any_of("C", &["A", "B"])]);
+        let requests = [fixed("A", false), fixed("B", false), fixed("C", true)];
+        let err = model.solve(&[false; 3], &requests, &Logger::new(0)).unwrap_err();
+        let conflict = err.downcast_ref::<Conflict>().unwrap();
+        assert_eq!(conflict.constraints.len(), 4);
+        assert_eq!(conflict.to_string(), "Conflicting constraints:\n  \
+                                          A must be disabled\n  \
+                                          B must be disabled\n  \
+                                          C must be enabled\n  \
+                                          C requires one of A, B");
+    }
+}
@@ -0,0 +1,256 @@
+use std::{error, fmt, path};
+use crate::logger::{Logger, Verbosity};
+use crate::{parse, ConfigEntry};
+
+/// Impact of a specification change on existing configs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// Existing configs may no longer validate or may change meaning
+    Breaking,
+    /// Existing configs remain valid
+    Compatible
+}
+
+/// Difference in a single option between two specifications
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpecChange {
+    Added {
+        option: String
+    },
+    Removed {
+        option: String
+    },
+    TypeChanged {
+        option: String,
+        old: String,
+        new: String
+    },
+    DefaultChanged {
+        option: String,
+        old: String,
+        new: String
+    },
+    /// Allowed values changed, `None` meaning any value of the type
+    ChoicesChanged {
+        option: String,
+        old: Option<Vec<String>>,
+        new: Option<Vec<String>>
+    },
+    DependsChanged {
+        option: String,
+        added: Vec<String>,
+        removed: Vec<String>
+    }
+}
+
+impl SpecChange {
+    /// Classify the change by whether configs written against the old
+    /// specification may stop validating against the new one, or resolve
+    /// to different values because they rely on a default
+    pub fn severity(&self) -> Severity {
+        match self {
+            SpecChange::Added { .. } => Severity::Compatible,
+            SpecChange::Removed { .. } | SpecChange::TypeChanged { .. } | SpecChange::DefaultChanged { .. } => Severity::Breaking,
+            SpecChange::ChoicesChanged { old, new, .. } => match (old, new) {
+                (_, None) => Severity::Compatible,
+                (None, Some(_)) => Severity::Breaking,
+                (Some(old), Some(new)) => match old.iter().all(|c| new.contains(c)) {
+                    true => Severity::Compatible,
+                    false => Severity::Breaking
+                }
+            },
+            SpecChange::DependsChanged { added, .. } => match added.is_empty() {
+                true => Severity::Compatible,
+                false => Severity::Breaking
+            }
+        }
+    }
+}
+
+fn format_choices(choices: &Option<Vec<String>>) -> String {
+    match choices {
+        Some(choices) => choices.join(", "),
+        None => "any".to_string()
+    }
+}
+
+impl fmt::Display for SpecChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpecChange::Added { option } => write!(f, "Added option `{}`", option),
+            SpecChange::Removed { option } => write!(f, "Removed option `{}`", option),
+            SpecChange::TypeChanged { option, old, new } =>
+                write!(f, "Changed type of `{}` from {} to {}", option, old, new),
+            SpecChange::DefaultChanged { option, old, new } =>
+                write!(f, "Changed default of `{}` from `{}` to `{}`", option, old, new),
+            SpecChange::ChoicesChanged { option, old, new } =>
+                write!(f, "Changed choices of `{}` from {} to {}", option, format_choices(old), format_choices(new)),
+            SpecChange::DependsChanged { option, added, removed } => {
+                write!(f, "Changed dependencies of `{}`", option)?;
+                if !added.is_empty() {
+                    write!(f, ", added {}", added.join(", "))?;
+                }
+                if !removed.is_empty() {
+                    write!(f, ", removed {}", removed.join(", "))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Compare the options of two specifications
+///
+/// Changes are reported in the order of the new specification, followed by
+/// options that were removed.
+pub fn spec_diff(old: &[ConfigEntry], new: &[ConfigEntry], log: &Logger) -> Vec<SpecChange> {
+    let mut changes = vec![];
+    for n in new {
+        let o = match old.iter().find(|o| o.name == n.name) {
+            Some(o) => o,
+            None => {
+                log.writeln(Verbosity::Lvl1, &format!("Option \"{}\" was added", n.name));
+                changes.push(SpecChange::Added { option: n.name.clone() });
+                continue;
+            }
+        };
+
+        let option = n.name.clone();
+        if o.type_name() != n.type_name() {
+            changes.push(SpecChange::TypeChanged {
+                option: option.clone(),
+                old: o.type_name().to_string(),
+                new: n.type_name().to_string()
+            });
+        }
+        else if o.default_value() != n.default_value() {
+            changes.push(SpecChange::DefaultChanged {
+                option: option.clone(),
+                old: o.default_value(),
+                new: n.default_value()
+            });
+        }
+
+        let (old_choices, new_choices) = (o.choices.as_ref().map(|c| c.0.clone()), n.choices.as_ref().map(|c| c.0.clone()));
+        if old_choices != new_choices {
+            changes.push(SpecChange::ChoicesChanged { option: option.clone(), old: old_choices, new: new_choices });
+        }
+
+        let added: Vec<String> = n.depends.iter().filter(|d| !o.depends.contains(d)).cloned().collect();
+        let removed: Vec<String> = o.depends.iter().filter(|d| !n.depends.contains(d)).cloned().collect();
+        if !added.is_empty() || !removed.is_empty() {
+            changes.push(SpecChange::DependsChanged { option, added, removed });
+        }
+    }
+
+    for o in old.iter().filter(|o| !new.iter().any(|n| n.name == o.name)) {
+        log.writeln(Verbosity::Lvl1, &format!("Option \"{}\" was removed", o.name));
+        changes.push(SpecChange::Removed { option: o.name.clone() });
+    }
+    changes
+}
+
+/// Format changes as a Markdown changelog, breaking changes first
+pub fn changelog(changes: &[SpecChange]) -> String {
+    let mut md = String::from("# Specification changes\n");
+    if changes.is_empty() {
+        md.push_str("\nNo changes.\n");
+    }
+    for (heading, severity) in [("Breaking changes", Severity::Breaking), ("Compatible changes", Severity::Compatible)] {
+        let section: Vec<&SpecChange> = changes.iter().filter(|c| c.severity() == severity).collect();
+        if section.is_empty() {
+            continue;
+        }
+        md.push_str(&format!("\n## {}\n\n", heading));
+        for change in section {
+            md.push_str(&format!("- {}\n", change));
+        }
+    }
+    md
+}
+
+/// Print the changes between the specifications at `old` and `new`
+pub fn print_spec_diff(old: &path::PathBuf, new: &path::PathBuf, markdown: bool, log: &Logger)
+    -> Result<(), Box<dyn error::Error>>
+{
+    let (old, new) = (parse::parse_spec(old)?, parse::parse_spec(new)?);
+    parse::spec_graph(&old)?;
+    parse::spec_graph(&new)?;
+    let changes = spec_diff(&old, &new, log);
+    if markdown {
+        print!("{}", changelog(&changes));
+        return Ok(());
+    }
+
+    if changes.is_empty() {
+        println!("Specifications are equivalent");
+    }
+    for change in &changes {
+        let severity = match change.severity() {
+            Severity::Breaking => "breaking",
+            Severity::Compatible => "compatible"
+        };
+        println!("{:10} {}", severity, change);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::specdiff::*;
+    use crate::test_util::{entry, with_choices};
+    use crate::{EntryType, Switch};
+
+    #[test]
+    fn classifies_changes() {
+        let old = vec![
+            entry("CONFIG_NET", &[], EntryType::Switch(Switch::Yes)),
+            entry("CONFIG_PORT", &[], EntryType::Int(80)),
+            with_choices(entry("CONFIG_LEVEL", &[], EntryType::String("info".to_string())), &["info", "trace"]),
+            entry("CONFIG_GONE", &[], EntryType::Switch(Switch::No))
+        ];
+        let new = vec![
+            entry("CONFIG_NET", &[], EntryType::Switch(Switch::No)),
+            entry("CONFIG_PORT", &["CONFIG_NET"], EntryType::String("80".to_string())),
+            with_choices(entry("CONFIG_LEVEL", &[], EntryType::String("info".to_string())), &["info", "trace", "debug"]),
+            entry("CONFIG_NEW", &[], EntryType::Switch(Switch::No))
+        ];
+        let changes = spec_diff(&old, &new, &Logger::new(0));
+        let severities: Vec<Severity> = changes.iter().map(|c| c.severity()).collect();
+        assert!(matches!(changes[0], SpecChange::DefaultChanged { .. }));
+        assert!(matches!(changes[1], SpecChange::TypeChanged { .. }));
+        assert!(matches!(changes[2], SpecChange::DependsChanged { .. }));
+        assert!(matches!(changes[3], SpecChange::ChoicesChanged { .. }));
+        assert!(matches!(changes[4], SpecChange::Added { .. }));
+        assert!(matches!(changes[5], SpecChange::Removed { .. }));
+        assert_eq!(severities, vec![Severity::Breaking, Severity::Breaking, Severity::Breaking,
+                                    Severity::Compatible, Severity::Compatible, Severity::Breaking]);
+    }
+
+    #[test]
+    fn restricting_choices_is_breaking() {
+        let change = SpecChange::ChoicesChanged {
+            option: "CONFIG_LEVEL".to_string(),
+            old: None,
+            new: Some(vec!["info".to_string()])
+        };
+        assert_eq!(change.severity(), Severity::Breaking);
+        assert_eq!(changelog(&[change]),
+                   "# Specification changes\n\n## Breaking changes\n\n- Changed choices of `CONFIG_LEVEL` from any to info\n");
+    }
+
+    #[test]
+    fn changing_defaults_is_breaking() {
+        let old = [entry("CONFIG_NET", &[], EntryType::Switch(Switch::Yes))];
+        let new = [entry("CONFIG_NET", &[], EntryType::Switch(Switch::No))];
+        let changes = spec_diff(&old, &new, &Logger::new(0));
+        assert_eq!(changes, vec![SpecChange::DefaultChanged {
+            option: "CONFIG_NET".to_string(),
+            old: "y".to_string(),
+            new: "n".to_string()
+        }]);
+        assert_eq!(changes[0].severity(), Severity::Breaking);
+        assert_eq!(changelog(&changes),
+                   "# Specification changes\n\n## Breaking changes\n\n- Changed default of `CONFIG_NET` from `y` to `n`\n");
+    }
+}
@@ -0,0 +1,178 @@
+use std::{error, fs, path};
+use crate::generate::{Context, Generator};
+use crate::logger::Verbosity;
+use crate::output::{Outcome, WriteMode};
+
+/// One small file per option, in the spirit of the kernel's `include/config`
+///
+/// Each file is named after its option and holds only that option's value.
+/// Since unchanged files are left untouched, build rules depending on a
+/// particular file are only triggered when that option changes.
+/// Files left from earlier splits of options no longer in the specification
+/// are removed, other files in the output directory are kept.
+#[derive(Debug)]
+pub struct Split;
+
+/// Check that an option name can be used as a file name inside the output directory
+fn file_name(name: &str) -> Result<&str, Box<dyn error::Error>> {
+    let mut components = path::Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(path::Component::Normal(n)), None) if n == name && !name.contains('\\') => Ok(name),
+        _ => Err(format!("Option name \"{}\" cannot be used as a file name", name).into())
+    }
+}
+
+/// Whether `contents` is what a split would write for an option called `name`
+fn is_option_file(name: &str, contents: &str) -> bool {
+    let set = contents.strip_prefix(name)
+                      .and_then(|rest| rest.strip_prefix(" = "));
+    match set {
+        Some(value) => value.ends_with('\n') && value.matches('\n').count() == 1,
+        None => contents == format!("# {} is not set\n", name)
+    }
+}
+
+impl Generator for Split {
+    fn name(&self) -> &str {
+        "split"
+    }
+
+    fn description(&self) -> &str {
+        "One file per option in the output directory"
+    }
+
+    fn generate(&self, ctx: &Context<'_>, output: &path::Path) -> Result<(), Box<dyn error::Error>> {
+        let resolved = ctx.resolved()?;
+        // Refuse before writing anything, names could otherwise escape the output directory
+        for res in resolved {
+            file_name(&res.entry.name)?;
+        }
+
+        if !output.is_dir() && ctx.mode == WriteMode::Write {
+            ctx.log.writeln(Verbosity::Lvl1, &format!("Creating directory {}", output.display()));
+            fs::create_dir_all(output)?;
+        }
+
+        let mut written = 0usize;
+        for res in resolved {
+            let contents = match &res.value {
+                Some(value) => format!("{} = {}\n", res.entry.name, value),
+                None => format!("# {} is not set\n", res.entry.name)
+            };
+            if ctx.write(&output.join(file_name(&res.entry.name)?), &contents)? == Outcome::Written {
+                written += 1;
+            }
+        }
+        ctx.log.writeln(Verbosity::Lvl1, &format!("Updated {} of {} option files", written, resolved.len()));
+
+        if !output.is_dir() {
+            return Ok(());
+        }
+        let mut removed = 0usize;
+        for file in fs::read_dir(output)? {
+            let path = file?.path();
+            let name = match path.file_name().and_then(|n| n.to_str()) {
+                Some(name) if path.is_file() => name,
+                _ => continue
+            };
+            if resolved.iter().any(|res| res.entry.name == name) {
+                continue;
+            }
+            if is_option_file(name, &fs::read_to_string(&path).unwrap_or_default())
+               && ctx.remove(&path)? == Outcome::Removed {
+                removed += 1;
+            }
+        }
+        if removed > 0 {
+            ctx.log.writeln(Verbosity::Lvl1, &format!("Removed {} option files no longer generated", removed));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::split::*;
+    use crate::generate::{self, Origin, Resolved};
+    use crate::logger::Logger;
+    use crate::test_util::entry;
+    use crate::{parse, ConfigEntry, EntryType, Switch};
+
+    fn entries(names: &[&str]) -> Vec<ConfigEntry> {
+        names.iter()
+             .map(|name| entry(name, &[], EntryType::Switch(Switch::Yes)))
+             .collect()
+    }
+
+    fn split(entries: &[ConfigEntry], values: &[Option<&str>], mode: WriteMode, output: &path::Path)
+        -> Result<bool, Box<dyn error::Error>>
+    {
+        let log = Logger::new(0);
+        let resolved: Vec<Resolved<'_>> = entries.iter()
+                                                 .zip(values)
+                                                 .map(|(entry, value)| Resolved {
+                                                     entry,
+                                                     value: value.map(|v| v.to_string()),
+                                                     origin: Origin::Explicit
+                                                 })
+                                                 .collect();
+        let graph = parse::spec_graph(entries)?;
+        let ctx = generate::Context::with_resolved(resolved, entries, &graph, mode, &[], &log);
+        Split.generate(&ctx, output)?;
+        Ok(ctx.is_stale())
+    }
+
+    fn temp_dir(name: &str) -> path::PathBuf {
+        std::env::temp_dir().join(format!("conftool-split-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn writes_one_file_per_option() -> Result<(), Box<dyn error::Error>> {
+        let dir = temp_dir("files");
+        let entries = entries(&["CONFIG_A", "CONFIG_B"]);
+        split(&entries, &[Some("y"), None], WriteMode::Write, &dir)?;
+        assert_eq!(fs::read_to_string(dir.join("CONFIG_A"))?, "CONFIG_A = y\n");
+        assert_eq!(fs::read_to_string(dir.join("CONFIG_B"))?, "# CONFIG_B is not set\n");
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn check_detects_drift() -> Result<(), Box<dyn error::Error>> {
+        let dir = temp_dir("check");
+        let entries = entries(&["CONFIG_A", "CONFIG_B"]);
+        split(&entries, &[Some("y"), Some("n")], WriteMode::Write, &dir)?;
+        assert!(!split(&entries, &[Some("y"), Some("n")], WriteMode::Check, &dir)?);
+        assert!(split(&entries, &[Some("y"), Some("y")], WriteMode::Check, &dir)?);
+        assert_eq!(fs::read_to_string(dir.join("CONFIG_B"))?, "CONFIG_B = n\n");
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn files_of_removed_options_are_removed() -> Result<(), Box<dyn error::Error>> {
+        let dir = temp_dir("removed");
+        split(&entries(&["CONFIG_A", "CONFIG_B", "CONFIG_C"]), &[Some("y"), None, Some("n")], WriteMode::Write, &dir)?;
+        fs::write(dir.join("Makefile"), "include CONFIG_A\n")?;
+
+        let entries = entries(&["CONFIG_A"]);
+        assert!(split(&entries, &[Some("y")], WriteMode::Check, &dir)?);
+        assert!(dir.join("CONFIG_B").exists());
+        split(&entries, &[Some("y")], WriteMode::Write, &dir)?;
+        assert!(!dir.join("CONFIG_B").exists());
+        assert!(!dir.join("CONFIG_C").exists());
+        assert_eq!(fs::read_to_string(dir.join("Makefile"))?, "include CONFIG_A\n");
+        assert!(!split(&entries, &[Some("y")], WriteMode::Check, &dir)?);
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn names_escaping_the_output_directory_are_refused() {
+        let dir = temp_dir("escape");
+        for name in ["../CONFIG_A", "CONFIG/A", "..", "/CONFIG_A", "CONFIG\\A"] {
+            assert!(split(&entries(&[name]), &[Some("y")], WriteMode::Write, &dir).is_err(), "{}", name);
+        }
+        assert!(!dir.exists());
+    }
+}
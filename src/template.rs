@@ -0,0 +1,448 @@
+use std::{error, fs, path};
+use crate::generate::{self, Origin, Resolved};
+use crate::graph::{state, Graph};
+use crate::logger::{Logger, Verbosity};
+use crate::output::{self, Outcome, WriteMode};
+use crate::{ConfigEntry, EntryType};
+
+/// Raw piece of a template
+#[derive(Debug, PartialEq)]
+enum Token {
+    Text(String),
+    /// Contents of `{{ ... }}` and its line number
+    Expr(String, usize),
+    /// Contents of `{% ... %}` and its line number
+    Block(String, usize)
+}
+
+/// Reference to an option or one of its fields, e.g. `CONFIG_FOO.help`
+#[derive(Debug, PartialEq)]
+struct Expr {
+    ident: String,
+    field: Option<String>,
+    line: usize
+}
+
+#[derive(Debug, PartialEq)]
+enum Cond {
+    Truthy(Expr),
+    Eq(Expr, String),
+    Ne(Expr, String)
+}
+
+#[derive(Debug, PartialEq)]
+enum Filter {
+    /// Only options of the given type
+    Type(String),
+    /// Only options depending, directly or indirectly, on the given one
+    Menu(String),
+    /// Only enabled options
+    Enabled
+}
+
+#[derive(Debug, PartialEq)]
+enum Node {
+    Text(String),
+    Expr(Expr),
+    If {
+        negate: bool,
+        cond: Cond,
+        then: Vec<Node>,
+        otherwise: Vec<Node>
+    },
+    For {
+        var: String,
+        filters: Vec<Filter>,
+        body: Vec<Node>
+    }
+}
+
+/// Split template into tokens
+///
+/// Block tags that occupy a line of their own consume the line, including
+/// its indentation and trailing newline, so that they leave no blank lines
+/// in the output.
+fn tokenize(src: &str) -> Result<Vec<Token>, Box<dyn error::Error>> {
+    let mut tokens = vec![];
+    let mut rest = src;
+    let mut line = 1usize;
+
+    while !rest.is_empty() {
+        let start = match rest.find("{{").into_iter().chain(rest.find("{%")).chain(rest.find("{#")).min() {
+            Some(start) => start,
+            None => {
+                tokens.push(Token::Text(rest.to_string()));
+                break;
+            }
+        };
+
+        let mut text = &rest[..start];
+        let (close, kind) = match &rest[start..start + 2] {
+            "{{" => ("}}", 0),
+            "{%" => ("%}", 1),
+            _ => ("#}", 2)
+        };
+        let end = match rest[start + 2..].find(close) {
+            Some(end) => start + 2 + end,
+            None => return Err(format!("Unterminated tag on line {}", line + text.matches('\n').count()).into())
+        };
+        let inner = rest[start + 2..end].trim().to_string();
+        let mut after = &rest[end + 2..];
+
+        let tag_line = line + text.matches('\n').count();
+        if kind != 0 {
+            let pos = src.len() - rest.len() + start;
+            let indent = pos - src[..pos].rfind('\n').map_or(0, |i| i + 1);
+            let trailing = after.len() - after.trim_start_matches([' ', '\t']).len();
+            let standalone = src[pos - indent..pos].trim().is_empty() &&
+                             (after[trailing..].starts_with('\n') || after[trailing..].is_empty());
+            if standalone {
+                text = &text[..text.len() - indent];
+                after = &after[trailing..];
+                if let Some(stripped) = after.strip_prefix('\n') {
+                    after = stripped;
+                }
+            }
+        }
+
+        if !text.is_empty() {
+            tokens.push(Token::Text(text.to_string()));
+        }
+        match kind {
+            0 => tokens.push(Token::Expr(inner, tag_line)),
+            1 => tokens.push(Token::Block(inner, tag_line)),
+            _ => ()
+        };
+        line += rest[..rest.len() - after.len()].matches('\n').count();
+        rest = after;
+    }
+
+    Ok(tokens)
+}
+
+fn parse_expr(s: &str, line: usize) -> Result<Expr, Box<dyn error::Error>> {
+    let valid = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    let (ident, field) = match s.split_once('.') {
+        Some((ident, field)) => (ident, Some(field.to_string())),
+        None => (s, None)
+    };
+    if !valid(ident) || !field.as_deref().is_none_or(valid) {
+        return Err(format!("Invalid expression \"{}\" on line {}", s, line).into());
+    }
+    Ok(Expr { ident: ident.to_string(), field, line })
+}
+
+fn parse_literal(s: &str, line: usize) -> Result<String, Box<dyn error::Error>> {
+    let s = s.trim();
+    if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
+        return Ok(s[1..s.len() - 1].to_string());
+    }
+    Err(format!("Expected quoted string, found \"{}\" on line {}", s, line).into())
+}
+
+fn parse_cond(s: &str, line: usize) -> Result<(bool, Cond), Box<dyn error::Error>> {
+    let (negate, s) = match s.strip_prefix("not ") {
+        Some(rest) => (true, rest.trim()),
+        None => (false, s)
+    };
+    // The expression ends at the first character it cannot contain, so that
+    // operators inside the literal are left alone
+    let end = s.find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')))
+               .unwrap_or(s.len());
+    let expr = parse_expr(&s[..end], line)?;
+    let rest = s[end..].trim_start();
+    let cond = if rest.is_empty() {
+        Cond::Truthy(expr)
+    }
+    else if let Some(literal) = rest.strip_prefix("==") {
+        Cond::Eq(expr, parse_literal(literal, line)?)
+    }
+    else if let Some(literal) = rest.strip_prefix("!=") {
+        Cond::Ne(expr, parse_literal(literal, line)?)
+    }
+    else {
+        return Err(format!("Expected == or != after \"{}\" on line {}", &s[..end], line).into());
+    };
+    Ok((negate, cond))
+}
+
+fn parse_for(s: &str, line: usize) -> Result<(String, Vec<Filter>), Box<dyn error::Error>> {
+    let words: Vec<&str> = s.split_whitespace().collect();
+    if words.len() < 3 || words[1] != "in" || words[2] != "options" {
+        return Err(format!("Expected \"for <var> in options\" on line {}", line).into());
+    }
+
+    let mut filters = vec![];
+    for word in &words[3..] {
+        let filter = match word.split_once('=') {
+            Some(("type", ty @ ("switch" | "string" | "integer"))) => Filter::Type(ty.to_string()),
+            Some(("menu", opt)) => Filter::Menu(opt.to_string()),
+            None if *word == "enabled" => Filter::Enabled,
+            _ => return Err(format!("Invalid loop filter \"{}\" on line {}", word, line).into())
+        };
+        filters.push(filter);
+    }
+    Ok((words[0].to_string(), filters))
+}
+
+/// Parse tokens until one of `until` is encountered, returning the nodes and the terminating tag
+fn parse_nodes<I>(tokens: &mut I, until: &[&str]) -> Result<(Vec<Node>, Option<String>), Box<dyn error::Error>>
+where
+    I: Iterator<Item = Token>
+{
+    let mut nodes = vec![];
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Text(text) => nodes.push(Node::Text(text)),
+            Token::Expr(expr, line) => nodes.push(Node::Expr(parse_expr(&expr, line)?)),
+            Token::Block(block, line) => {
+                let (keyword, rest) = block.split_once(char::is_whitespace)
+                                           .map_or((block.as_str(), ""), |(k, r)| (k, r.trim()));
+                if until.contains(&keyword) {
+                    return Ok((nodes, Some(keyword.to_string())));
+                }
+                match keyword {
+                    "if" => {
+                        let (negate, cond) = parse_cond(rest, line)?;
+                        let (then, end) = parse_nodes(tokens, &["else", "endif"])?;
+                        let otherwise = match end.as_deref() {
+                            Some("else") => match parse_nodes(tokens, &["endif"])? {
+                                (otherwise, Some(_)) => otherwise,
+                                (_, None) => return Err(format!("Unterminated if on line {}", line).into())
+                            },
+                            Some(_) => vec![],
+                            None => return Err(format!("Unterminated if on line {}", line).into())
+                        };
+                        nodes.push(Node::If { negate, cond, then, otherwise });
+                    },
+                    "for" => {
+                        let (var, filters) = parse_for(rest, line)?;
+                        let body = match parse_nodes(tokens, &["endfor"])? {
+                            (body, Some(_)) => body,
+                            (_, None) => return Err(format!("Unterminated for on line {}", line).into())
+                        };
+                        nodes.push(Node::For { var, filters, body });
+                    },
+                    _ => return Err(format!("Unexpected \"{}\" on line {}", keyword, line).into())
+                };
+            }
+        };
+    }
+    Ok((nodes, None))
+}
+
+fn parse(src: &str) -> Result<Vec<Node>, Box<dyn error::Error>> {
+    let mut tokens = tokenize(src)?.into_iter();
+    let (nodes, _) = parse_nodes(&mut tokens, &[])?;
+    Ok(nodes)
+}
+
+fn is_enabled(res: &Resolved<'_>) -> bool {
+    match (&res.entry.enttype, &res.value) {
+        (EntryType::Switch(_), Some(value)) => value == "y",
+        (_, value) => value.is_some()
+    }
+}
+
+struct Renderer<'a, 'r> {
+    resolved: &'r [Resolved<'a>],
+    graph: &'r Graph<&'r str, state::Complete>,
+    /// Loop variables currently in scope
+    scope: Vec<(&'r str, &'r Resolved<'a>)>,
+    log: &'r Logger
+}
+
+impl<'a, 'r> Renderer<'a, 'r> {
+    fn lookup(&self, expr: &Expr) -> Result<&'r Resolved<'a>, Box<dyn error::Error>> {
+        if let Some((_, res)) = self.scope.iter().rev().find(|(var, _)| *var == expr.ident) {
+            return Ok(res);
+        }
+        match self.resolved.iter().find(|r| r.entry.name == expr.ident) {
+            Some(res) => Ok(res),
+            None => Err(format!("Unknown identifier \"{}\" on line {}", expr.ident, expr.line).into())
+        }
+    }
+
+    fn value(&self, expr: &Expr) -> Result<String, Box<dyn error::Error>> {
+        let res = self.lookup(expr)?;
+        let ent = res.entry;
+        let value = match expr.field.as_deref() {
+            None | Some("value") => res.value.clone().unwrap_or_default(),
+            Some("name") => ent.name.clone(),
+            Some("help") => ent.help.clone(),
+            Some("type") => ent.type_name().to_string(),
+            Some("default") => ent.default_value(),
+            Some("depends") => ent.depends.to_string(),
+            Some("choices") => ent.choices.as_ref().map(|c| c.to_string()).unwrap_or_default(),
+            Some("origin") => match res.origin {
+                Origin::Explicit => "explicit",
+                Origin::Default => "default",
+                Origin::Disabled => "disabled"
+            }.to_string(),
+            Some(field) => return Err(format!("Unknown field \"{}\" on line {}", field, expr.line).into())
+        };
+        Ok(value)
+    }
+
+    fn eval(&self, cond: &Cond) -> Result<bool, Box<dyn error::Error>> {
+        match cond {
+            Cond::Truthy(expr) if expr.field.is_none() => Ok(is_enabled(self.lookup(expr)?)),
+            Cond::Truthy(expr) => Ok(!self.value(expr)?.is_empty()),
+            Cond::Eq(expr, lit) => Ok(self.value(expr)? == *lit),
+            Cond::Ne(expr, lit) => Ok(self.value(expr)? != *lit)
+        }
+    }
+
+    fn matches(&self, res: &Resolved<'a>, filters: &[Filter]) -> Result<bool, Box<dyn error::Error>> {
+        for filter in filters {
+            let keep = match filter {
+                Filter::Type(ty) => res.entry.type_name() == ty,
+                Filter::Menu(opt) => match self.graph.dependencies_of(&res.entry.name.as_str()) {
+                    Ok(deps) => deps.contains(&opt.as_str()),
+                    Err(_) => false
+                },
+                Filter::Enabled => is_enabled(res)
+            };
+            if !keep {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn render(&mut self, nodes: &'r [Node], out: &mut String) -> Result<(), Box<dyn error::Error>> {
+        for node in nodes {
+            match node {
+                Node::Text(text) => out.push_str(text),
+                Node::Expr(expr) => out.push_str(&self.value(expr)?),
+                Node::If { negate, cond, then, otherwise } => {
+                    if self.eval(cond)? != *negate {
+                        self.render(then, out)?;
+                    }
+                    else {
+                        self.render(otherwise, out)?;
+                    }
+                },
+                Node::For { var, filters, body } => {
+                    if let Some(Filter::Menu(opt)) = filters.iter().find(|f| matches!(f, Filter::Menu(_))) {
+                        if !self.resolved.iter().any(|r| r.entry.name == *opt) {
+                            return Err(format!("Invalid config option \"{}\" in menu filter", opt).into());
+                        }
+                    }
+                    for res in self.resolved {
+                        if !self.matches(res, filters)? {
+                            continue;
+                        }
+                        self.log.writeln(Verbosity::Lvl3, &format!("Rendering loop body for \"{}\"", res.entry.name));
+                        self.scope.push((var, res));
+                        let res = self.render(body, out);
+                        self.scope.pop();
+                        res?;
+                    }
+                }
+            };
+        }
+        Ok(())
+    }
+}
+
+/// Render template source against an already resolved config
+pub fn render_str(src: &str, resolved: &[Resolved<'_>], graph: &Graph<&str, state::Complete>, log: &Logger)
+    -> Result<String, Box<dyn error::Error>>
+{
+    let nodes = parse(src)?;
+    let mut renderer = Renderer { resolved, graph, scope: vec![], log };
+    let mut out = String::with_capacity(src.len());
+    renderer.render(&nodes, &mut out)?;
+    Ok(out)
+}
+
+/// Render the template at `template` using the current config and write the result to `output`
+pub fn render(template: &path::PathBuf, output: &path::Path, config: &path::PathBuf, entries: &[ConfigEntry],
+              graph: &Graph<&str, state::Complete>, mode: WriteMode, log: &Logger)
+    -> Result<(), Box<dyn error::Error>>
+{
+    let src = fs::read_to_string(template)?;
+    let resolved = generate::resolve_config(config, entries, graph, log)?;
+    log.writeln(Verbosity::Lvl1, &format!("Rendering template {}", template.display()));
+    let out = render_str(&src, &resolved, graph, log)?;
+    log.writeln(Verbosity::Lvl2, &format!("Writing rendered template to {}", output.display()));
+    if output::write(output, &out, mode, log)? == Outcome::Stale {
+        return Err(format!("Rendered output {} is out of date", output.display()).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::template::*;
+    use crate::test_util::entry;
+    use crate::Switch;
+
+    fn entries() -> Vec<ConfigEntry> {
+        let entries = vec![
+            entry("CONFIG_NET", &[], EntryType::Switch(Switch::Yes)),
+            entry("CONFIG_NET_PORT", &["CONFIG_NET"], EntryType::Int(80)),
+            entry("CONFIG_DEBUG", &[], EntryType::Switch(Switch::No)),
+            entry("CONFIG_NAME", &[], EntryType::String("board".to_string()))
+        ];
+        entries.into_iter()
+               .map(|e| ConfigEntry { help: format!("Help for {}", e.name), ..e })
+               .collect()
+    }
+
+    fn render(src: &str) -> Result<String, Box<dyn error::Error>> {
+        let entries = entries();
+        let log = Logger::new(0);
+        let graph = crate::parse::spec_graph(&entries)?;
+        let resolved = generate::resolve(&[], &entries, &graph, &log)?;
+        render_str(src, &resolved, &graph, &log)
+    }
+
+    #[test]
+    fn substitutes_values_and_fields() -> Result<(), Box<dyn error::Error>> {
+        assert_eq!(render("port={{ CONFIG_NET_PORT }}")?, "port=80");
+        assert_eq!(render("{{CONFIG_NAME.help}} ({{ CONFIG_NAME.type }})")?, "Help for CONFIG_NAME (string)");
+        Ok(())
+    }
+
+    #[test]
+    fn conditionals_on_switches() -> Result<(), Box<dyn error::Error>> {
+        let src = "{% if CONFIG_DEBUG %}debug{% else %}release{% endif %}";
+        assert_eq!(render(src)?, "release");
+        let src = "{% if not CONFIG_DEBUG %}release{% endif %}";
+        assert_eq!(render(src)?, "release");
+        let src = "{% if CONFIG_NAME == \"board\" %}match{% endif %}";
+        assert_eq!(render(src)?, "match");
+        Ok(())
+    }
+
+    #[test]
+    fn operators_inside_literals_are_not_split_on() -> Result<(), Box<dyn error::Error>> {
+        let src = "{% if CONFIG_NAME != \"a==b\" %}differs{% endif %}";
+        assert_eq!(render(src)?, "differs");
+        let src = "{% if CONFIG_NAME==\"a!=b\" %}match{% else %}differs{% endif %}";
+        assert_eq!(render(src)?, "differs");
+        assert!(render("{% if CONFIG_NAME = \"board\" %}{% endif %}").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn loops_with_filters() -> Result<(), Box<dyn error::Error>> {
+        let src = "{% for opt in options type=switch %}\n{{ opt.name }}={{ opt }}\n{% endfor %}\n";
+        assert_eq!(render(src)?, "CONFIG_NET=y\nCONFIG_DEBUG=n\n");
+        let src = "{% for opt in options menu=CONFIG_NET %}{{ opt.name }}{% endfor %}";
+        assert_eq!(render(src)?, "CONFIG_NET_PORT");
+        let src = "{% for opt in options enabled type=switch %}{{ opt.name }}{% endfor %}";
+        assert_eq!(render(src)?, "CONFIG_NET");
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_malformed_templates() {
+        assert!(render("{% if CONFIG_NET %}").is_err());
+        assert!(render("{{ CONFIG_UNKNOWN }}").is_err());
+        assert!(render("{% for opt in options type=float %}{% endfor %}").is_err());
+        assert!(render("{{ CONFIG_NET").is_err());
+    }
+}
@@ -0,0 +1,26 @@
+use crate::display_vec::DisplayVec;
+use crate::{ConfigEntry, EntryType};
+
+/// Specification entry without choices or help text
+pub fn entry(name: &str, depends: &[&str], enttype: EntryType) -> ConfigEntry {
+    ConfigEntry {
+        name: name.to_string(),
+        depends: DisplayVec(depends.iter().map(|s| s.to_string()).collect()),
+        enttype,
+        choices: None,
+        help: String::new()
+    }
+}
+
+/// `ent` restricted to the given choices
+pub fn with_choices(ent: ConfigEntry, choices: &[&str]) -> ConfigEntry {
+    ConfigEntry {
+        choices: Some(DisplayVec(choices.iter().map(|s| s.to_string()).collect())),
+        ..ent
+    }
+}
+
+/// Config options as parsed from a config file
+pub fn kvpairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
@@ -0,0 +1,286 @@
+use std::{error, fmt, io, path};
+use crate::graph::{state, Graph};
+use crate::logger::{Logger, Verbosity};
+use crate::{generate, manipulate, parse, ConfigEntry};
+
+/// Modification made while bringing a config up to date with its specification
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// Option no longer present in the specification
+    Removed {
+        option: String,
+        value: String
+    },
+    /// Value no longer valid, replaced by the default
+    Clamped {
+        option: String,
+        old: String,
+        new: String
+    },
+    /// Option missing from the config, set to its default
+    Added {
+        option: String,
+        value: String
+    },
+    /// Option disabled or dropped because one of its dependencies is disabled
+    Disabled {
+        option: String,
+        old: String,
+        dependency: String
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Removed { option, value } =>
+                write!(f, "Removed {} = {} (not in specification)", option, value),
+            Change::Clamped { option, old, new } =>
+                write!(f, "Changed {}: {} -> {} (invalid value)", option, old, new),
+            Change::Added { option, value } =>
+                write!(f, "Added {} = {} (default)", option, value),
+            Change::Disabled { option, old, dependency } =>
+                write!(f, "Disabled {} = {} (dependency {} disabled)", option, old, dependency)
+        }
+    }
+}
+
+/// Upgraded config and the changes made to it
+#[derive(Debug)]
+pub struct Upgrade {
+    pub kvpairs: Vec<(String, String)>,
+    pub changes: Vec<Change>
+}
+
+/// Disable enabled options whose dependencies are not all enabled
+///
+/// Switches are set to `n`, other options are removed. Repeated until no
+/// further changes are needed, as each change may disable other options.
+pub fn enforce_dependencies(kvpairs: &mut Vec<(String, String)>, entries: &[ConfigEntry],
+                            graph: &Graph<&str, state::Complete>, log: &Logger)
+    -> Result<Vec<Change>, Box<dyn error::Error>>
+{
+    let is_enabled = |kvpairs: &[(String, String)], opt: &str| {
+        kvpairs.iter().any(|(k, v)| k == opt && v == "y")
+    };
+
+    let mut changes = vec![];
+    loop {
+        let mut change = None;
+        for (i, (opt, val)) in kvpairs.iter().enumerate() {
+            let ent = match entries.iter().find(|e| e.name == *opt) {
+                Some(ent) => ent,
+                None => continue
+            };
+            if ent.is_switch() && val == "n" {
+                continue;
+            }
+            let deps = graph.dependencies_of(&opt.as_str())?;
+            if let Some(dep) = deps.iter().find(|d| !is_enabled(kvpairs, d)) {
+                log.writeln(Verbosity::Lvl2, &format!("Dependency \"{}\" of \"{}\" is disabled", dep, opt));
+                change = Some((i, ent.is_switch(), dep.to_string()));
+                break;
+            }
+        }
+
+        let (i, switch, dependency) = match change {
+            Some(change) => change,
+            None => break
+        };
+        let (option, old) = kvpairs[i].clone();
+        if switch {
+            kvpairs[i].1 = "n".to_string();
+        }
+        else {
+            kvpairs.remove(i);
+        }
+        changes.push(Change::Disabled { option, old, dependency });
+    }
+    Ok(changes)
+}
+
+/// Bring `kvpairs` up to date with the specification
+///
+/// Valid values are kept, options no longer in the specification are dropped,
+/// invalid values are replaced by their defaults and new options are set to
+/// their defaults if their dependencies allow. Finally, options whose
+/// dependencies ended up disabled are disabled as well.
+pub fn upgrade(kvpairs: &[(String, String)], entries: &[ConfigEntry],
+               graph: &Graph<&str, state::Complete>, log: &Logger)
+    -> Result<Upgrade, Box<dyn error::Error>>
+{
+    let mut changes = vec![];
+    let mut upgraded: Vec<(String, String)> = Vec::with_capacity(entries.len());
+
+    for (opt, val) in kvpairs {
+        let ent = match entries.iter().find(|e| e.name == *opt) {
+            Some(ent) => ent,
+            None => {
+                log.writeln(Verbosity::Lvl1, &format!("Dropping unknown option \"{}\"", opt));
+                changes.push(Change::Removed { option: opt.clone(), value: val.clone() });
+                continue;
+            }
+        };
+        if upgraded.iter().any(|(k, _)| k == opt) {
+            log.writeln(Verbosity::Lvl1, &format!("Dropping duplicate of option \"{}\"", opt));
+            changes.push(Change::Removed { option: opt.clone(), value: val.clone() });
+            continue;
+        }
+
+        match manipulate::validate_value(opt, val, ent) {
+            Ok(()) => upgraded.push((opt.clone(), val.clone())),
+            Err(err) => {
+                log.writeln(Verbosity::Lvl1, &err);
+                let new = ent.default_value();
+                changes.push(Change::Clamped { option: opt.clone(), old: val.clone(), new: new.clone() });
+                upgraded.push((opt.clone(), new));
+            }
+        };
+    }
+
+    for res in generate::resolve(&upgraded, entries, graph, log)? {
+        if res.origin != generate::Origin::Default {
+            continue;
+        }
+        // Safe to unwrap, defaulted options always have a value
+        let value = res.value.unwrap();
+        changes.push(Change::Added { option: res.entry.name.clone(), value: value.clone() });
+        upgraded.push((res.entry.name.clone(), value));
+    }
+
+    changes.extend(enforce_dependencies(&mut upgraded, entries, graph, log)?);
+    Ok(Upgrade { kvpairs: upgraded, changes })
+}
+
+/// Upgrade the config at `path` to the current specification, printing every change made
+pub fn olddefconfig(path: &path::PathBuf, entries: &[ConfigEntry], graph: &Graph<&str, state::Complete>, log: &Logger)
+    -> Result<(), Box<dyn error::Error>>
+{
+    let kvpairs = parse::parse_config(path, None)?;
+    let upgrade = upgrade(&kvpairs, entries, graph, log)?;
+
+    if upgrade.changes.is_empty() {
+        println!("Config is up to date");
+    }
+    for change in &upgrade.changes {
+        println!("{}", change);
+    }
+
+    manipulate::write_config(&upgrade.kvpairs, path, log)
+}
+
+/// Ask for values of options present in the specification but missing from `kvpairs`
+///
+/// Options are visited in dependency order and skipped if any of their dependencies
+/// is disabled. An empty answer, or end of input, selects the default. Answers are
+/// validated the same way as by [`manipulate::set`].
+pub fn prompt_missing<R, W>(kvpairs: &mut Vec<(String, String)>, entries: &[ConfigEntry],
+                            graph: &Graph<&str, state::Complete>, input: &mut R, output: &mut W, log: &Logger)
+    -> Result<(), Box<dyn error::Error>>
+where
+    R: io::BufRead,
+    W: io::Write
+{
+    let missing: Vec<&ConfigEntry> = generate::dependency_order(entries, graph)?
+        .into_iter()
+        .filter(|e| !kvpairs.iter().any(|(k, _)| *k == e.name))
+        .collect();
+    for ent in missing {
+        let deps = graph.dependencies_of(&ent.name.as_str())?;
+        if let Some(dep) = deps.iter().find(|d| !kvpairs.iter().any(|(k, v)| k == *d && v == "y")) {
+            log.writeln(Verbosity::Lvl1, &format!("Skipping \"{}\" due to disabled dependency \"{}\"", ent.name, dep));
+            continue;
+        }
+
+        writeln!(output, "\n{}", ent)?;
+        let value = loop {
+            write!(output, "{} [{}]: ", ent.name, ent.default_value())?;
+            output.flush()?;
+
+            let mut answer = String::new();
+            if input.read_line(&mut answer)? == 0 {
+                writeln!(output)?;
+                break ent.default_value();
+            }
+            let answer = match answer.trim() {
+                "" => ent.default_value(),
+                answer => answer.to_string()
+            };
+            match manipulate::validate_value(&ent.name, &answer, ent) {
+                Ok(()) => break answer,
+                Err(err) => writeln!(output, "{}", err)?
+            };
+        };
+
+        log.writeln(Verbosity::Lvl2, &format!("Setting \"{}\" to \"{}\"", ent.name, value));
+        kvpairs.push((ent.name.clone(), value));
+    }
+    Ok(())
+}
+
+/// Interactively fill in options missing from the config at `path`
+pub fn oldconfig(path: &path::PathBuf, entries: &[ConfigEntry], graph: &Graph<&str, state::Complete>, log: &Logger)
+    -> Result<(), Box<dyn error::Error>>
+{
+    let mut kvpairs = if path.exists() {
+        parse::parse_config(path, None)?
+    }
+    else {
+        vec![]
+    };
+
+    let stdin = io::stdin();
+    prompt_missing(&mut kvpairs, entries, graph, &mut stdin.lock(), &mut io::stdout(), log)?;
+    manipulate::write_config(&kvpairs, path, log)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::upgrade::*;
+    use crate::test_util::{entry, kvpairs};
+    use crate::{EntryType, Switch};
+
+    fn entries() -> Vec<ConfigEntry> {
+        vec![
+            entry("CONFIG_NET_PORT", &["CONFIG_NET"], EntryType::Int(80)),
+            entry("CONFIG_NET", &[], EntryType::Switch(Switch::Yes)),
+            entry("CONFIG_DEBUG", &[], EntryType::Switch(Switch::No))
+        ]
+    }
+
+    #[test]
+    fn upgrade_reports_every_change() -> Result<(), Box<dyn error::Error>> {
+        let config = kvpairs(&[("CONFIG_NET", "n"), ("CONFIG_NET_PORT", "x"), ("CONFIG_GONE", "y")]);
+        let entries = entries();
+        let upgrade = upgrade(&config, &entries, &parse::spec_graph(&entries)?, &Logger::new(0))?;
+        assert_eq!(upgrade.kvpairs, kvpairs(&[("CONFIG_NET", "n"), ("CONFIG_DEBUG", "n")]));
+        assert_eq!(upgrade.changes.len(), 4);
+        assert!(matches!(&upgrade.changes[0], Change::Clamped { option, .. } if option == "CONFIG_NET_PORT"));
+        assert!(matches!(&upgrade.changes[1], Change::Removed { option, .. } if option == "CONFIG_GONE"));
+        assert!(matches!(&upgrade.changes[2], Change::Added { option, .. } if option == "CONFIG_DEBUG"));
+        assert!(matches!(&upgrade.changes[3], Change::Disabled { option, .. } if option == "CONFIG_NET_PORT"));
+        Ok(())
+    }
+
+    #[test]
+    fn prompts_in_dependency_order_and_revalidates() -> Result<(), Box<dyn error::Error>> {
+        let mut config = kvpairs(&[("CONFIG_DEBUG", "n")]);
+        let mut input = io::Cursor::new("\nabc\n8080\n");
+        let mut output = vec![];
+        let entries = entries();
+        prompt_missing(&mut config, &entries, &parse::spec_graph(&entries)?, &mut input, &mut output, &Logger::new(0))?;
+        assert_eq!(config, kvpairs(&[("CONFIG_DEBUG", "n"), ("CONFIG_NET", "y"), ("CONFIG_NET_PORT", "8080")]));
+        assert!(String::from_utf8(output)?.contains("Invalid value \"abc\""));
+        Ok(())
+    }
+
+    #[test]
+    fn skips_options_with_disabled_dependencies() -> Result<(), Box<dyn error::Error>> {
+        let mut config = kvpairs(&[("CONFIG_DEBUG", "n")]);
+        let mut input = io::Cursor::new("n\n");
+        let entries = entries();
+        prompt_missing(&mut config, &entries, &parse::spec_graph(&entries)?, &mut input, &mut io::sink(), &Logger::new(0))?;
+        assert_eq!(config, kvpairs(&[("CONFIG_DEBUG", "n"), ("CONFIG_NET", "n")]));
+        Ok(())
+    }
+}
@@ -1,12 +1,11 @@
 use crate::{display_vec, parse, ConfigEntry, EntryType};
-use crate::EntryType::Switch;
 use crate::graph::{state, Graph};
 use crate::logger::{Logger, Verbosity};
 use regex::Regex;
 use std::{collections,hash,error,fmt,fs,path};
 
 
-fn validate_line_format<T>(lines: &[T], log: &Logger) -> Result<(), Box<dyn error::Error>>
+pub(crate) fn validate_line_format<T>(lines: &[T], log: &Logger) -> Result<(), Box<dyn error::Error>>
 where
     T: AsRef<str> + fmt::Display
 {
@@ -34,9 +33,9 @@ fn validate_options<T>(kvpairs: &[(T, T)], entries: &[ConfigEntry], log: &Logger
 where
     T: AsRef<str> + fmt::Display
 {
-    let entnames: Vec<&str> = entries.iter()
-                                     .map(|e| e.name.as_ref())
-                                     .collect();
+    let entnames: collections::HashSet<&str> = entries.iter()
+                                                      .map(|e| e.name.as_ref())
+                                                      .collect();
     let mut valid = true;
     for (opt, _) in kvpairs {
         log.writeln(Verbosity::Lvl3, &format!("Checking validity of option {}", opt));
@@ -64,12 +63,13 @@ fn validate_values<T>(kvpairs: &[(T, T)], entries: &[ConfigEntry], log: &Logger)
 where
     T: AsRef<str> + fmt::Display
 {
+    let ents: collections::HashMap<&str, &ConfigEntry> = entries.iter()
+                                                                .map(|e| (e.name.as_ref(), e))
+                                                                .collect();
     let mut valid = true;
     for (option, value) in kvpairs {
         log.writeln(Verbosity::Lvl3, &format!("Checking validity of value {} for {}", value, option));
-        let ent = entries.iter()
-                         .find(|e| e.name == option.as_ref())
-                         .unwrap();
+        let ent = ents[option.as_ref()];
         let choices = match ent.enttype {
             EntryType::Switch(_) => vec!["y", "n"],
             _ => {
@@ -127,41 +127,57 @@ where
     T: AsRef<str> + fmt::Debug + fmt::Display + Clone +
        Eq + hash::Hash
 {
-    let mut missing: collections::HashMap<T, (Cause, Vec<&T>)> = collections::HashMap::new();
-    for (opt, val) in kvpairs {
-        let ent = entries.iter().find(|e| e.name == opt.as_ref()).unwrap();
-        if let Switch(_) = ent.enttype {
-            if "n" == val.as_ref() {
+    let values: collections::HashMap<&str, &T> = kvpairs.iter()
+                                                        .map(|(k, v)| (k.as_ref(), v))
+                                                        .collect();
+    let switches: collections::HashSet<&str> = entries.iter()
+                                                      .filter(|e| e.is_switch())
+                                                      .map(|e| e.name.as_ref())
+                                                      .collect();
+    let is_enabled = |opt: &T| match values.get(opt.as_ref()) {
+        Some(val) => !switches.contains(opt.as_ref()) || val.as_ref() == "y",
+        None => false
+    };
+    let enabled: Vec<&T> = kvpairs.iter()
+                                  .map(|(opt, _)| opt)
+                                  .filter(|opt| is_enabled(opt))
+                                  .collect();
+
+    // Enabled options have their own dependencies checked, so traversal only
+    // needs to continue past dependencies which are not enabled
+    let mut visited: collections::HashSet<T> = collections::HashSet::new();
+    let mut unmet: Vec<T> = vec![];
+    let mut to_traverse: Vec<T> = vec![];
+    for opt in &enabled {
+        log.writeln(Verbosity::Lvl1, &format!("Checking dependencies for {}", opt));
+        to_traverse.extend(graph.direct_dependencies_of(opt)?);
+        while let Some(dep) = to_traverse.pop() {
+            if !visited.insert(dep.clone()) {
                 continue;
             }
-        }
-        log.writeln(Verbosity::Lvl1, &format!("Checking dependencies for {}", opt));
-        let deps = graph.dependencies_of(opt)?;
-        log.writeln(Verbosity::Lvl3, &format!("Dependencies: {:?}", deps));
-
-        for dep in deps {
             log.writeln(Verbosity::Lvl2, &format!("Checking that {} is set", dep));
-            if let Some((_, val)) = kvpairs.iter().find(|(k, _)| k.as_ref() == dep.as_ref()) {
-                if val.as_ref() != "y" {
-                    if let Some((_, opts)) = missing.get_mut(&dep) {
-                        opts.push(opt);
-                    }
-                    else if !missing.insert(dep.clone(), (Cause::NotSet, vec![opt])).is_none() {
-                        return Err(format!("Dependency {} already present in map", dep).into());
-                    }
-                }
-            }
-            else {
-                if let Some((_, opts)) = missing.get_mut(&dep) {
-                    opts.push(opt);
-                }
-                else if !missing.insert(dep.clone(), (Cause::NotListed, vec![opt])).is_none() {
-                    return Err(format!("Dependency {} already present in map", dep).into());
-                }
+            if values.get(dep.as_ref()).is_some_and(|val| val.as_ref() == "y") {
+                continue;
             }
+            to_traverse.extend(graph.direct_dependencies_of(&dep)?);
+            unmet.push(dep);
         }
     }
 
+    let mut missing: collections::HashMap<T, (Cause, Vec<&T>)> = collections::HashMap::new();
+    for dep in unmet {
+        let dependents: collections::HashSet<T> = graph.dependent_vertices(&dep)?.into_iter().collect();
+        let opts: Vec<&T> = enabled.iter()
+                                   .copied()
+                                   .filter(|opt| dependents.contains(opt))
+                                   .collect();
+        let cause = match values.contains_key(dep.as_ref()) {
+            true => Cause::NotSet,
+            false => Cause::NotListed
+        };
+        missing.insert(dep, (cause, opts));
+    }
+
     if !missing.is_empty() {
         for (dep, (cause, opts)) in missing {
             let opts = display_vec::DisplayVec(opts);
@@ -173,7 +189,8 @@ where
     Ok(())
 }
 
-pub fn validate_config(path: &path::PathBuf, entries: &[ConfigEntry], log: &Logger)
+pub fn validate_config(path: &path::PathBuf, entries: &[ConfigEntry],
+                       graph: &Graph<&str, state::Complete>, log: &Logger)
     -> Result<(), Box<dyn error::Error>>
 {
     let lines: Vec<String> = fs::read_to_string(path)?
@@ -183,14 +200,20 @@ pub fn validate_config(path: &path::PathBuf, entries: &[ConfigEntry], log: &Logg
     validate_line_format(&lines, log)?;
     let kvpairs = parse::parse_config(path, Some(lines))?;
 
-    validate_options(&kvpairs, &entries, log)?;
-    validate_values(&kvpairs, &entries, log)?;
+    validate_kvpairs(&kvpairs, entries, graph, log)
+}
+
+/// Validate options, values and dependencies of an already parsed config
+pub fn validate_kvpairs(kvpairs: &[(String, String)], entries: &[ConfigEntry],
+                        graph: &Graph<&str, state::Complete>, log: &Logger)
+    -> Result<(), Box<dyn error::Error>>
+{
+    validate_options(kvpairs, entries, log)?;
+    validate_values(kvpairs, entries, log)?;
 
-    let graph = Graph::<&str, state::Incomplete>::from(entries);
-    let graph = graph.into_complete()?;
     let slice: Vec<(&str, &str)> = kvpairs.iter()
                                           .map(|(k, v)| (k.as_ref(), v.as_ref()))
                                           .collect();
-    check_dependencies(&graph, &slice, &entries, log)?;
+    check_dependencies(graph, &slice, entries, log)?;
     Ok(())
 }
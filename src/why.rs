@@ -0,0 +1,262 @@
+use std::{collections, error, fmt, path};
+use crate::generate::{self, Origin, Resolved};
+use crate::graph::{state, Graph};
+use crate::logger::{Logger, Verbosity};
+use crate::ConfigEntry;
+
+/// Reasons for the current value of an option
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    pub option: String,
+    /// Effective value, `None` if the option is disabled
+    pub value: Option<String>,
+    pub origin: Origin,
+    /// Chains of enabled options, each depending on the next, ending in this option
+    pub required_by: Vec<Vec<String>>,
+    /// Chains of disabled dependencies blocking this option, one per path
+    pub blocked_by: Vec<Blocker>
+}
+
+/// Chain of disabled dependencies blocking an option
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Blocker {
+    /// Dependencies from the blocked option to the disabled option at the end
+    pub chain: Vec<String>,
+    /// Where the value of the last option in `chain` comes from
+    pub origin: Origin
+}
+
+fn find<'a, 'r>(resolved: &'r [Resolved<'a>], option: &str) -> &'r Resolved<'a> {
+    // Safe to unwrap, resolved options cover the whole specification
+    resolved.iter().find(|r| r.entry.name == option).unwrap()
+}
+
+fn is_enabled(resolved: &[Resolved<'_>], option: &str) -> bool {
+    find(resolved, option).effective_value() == Some("y")
+}
+
+/// Whether an option is enabled, or for non-switches, has a value
+fn is_active(res: &Resolved<'_>) -> bool {
+    match res.entry.is_switch() {
+        true => res.value.as_deref() == Some("y"),
+        false => res.value.is_some()
+    }
+}
+
+/// Shortest chain of disabled dependencies from `option` to each disabled
+/// option whose own dependencies are all enabled
+fn blocking_chains(option: &str, resolved: &[Resolved<'_>], graph: &Graph<&str, state::Complete>)
+    -> Result<Vec<Blocker>, Box<dyn error::Error>>
+{
+    // Breadth-first over disabled dependencies, remembering how each was reached
+    let mut reached_from: collections::HashMap<&str, &str> = collections::HashMap::new();
+    let mut queue = collections::VecDeque::from([option]);
+    let mut blockers = vec![];
+    while let Some(current) = queue.pop_front() {
+        let disabled: Vec<&str> = graph.direct_dependencies_of(&current)?
+            .into_iter()
+            .filter(|d| !is_enabled(resolved, d))
+            .collect();
+        if disabled.is_empty() && current != option {
+            blockers.push(current);
+        }
+        for dep in disabled {
+            if dep != option && !reached_from.contains_key(dep) {
+                reached_from.insert(dep, current);
+                queue.push_back(dep);
+            }
+        }
+    }
+
+    Ok(blockers.into_iter()
+               .map(|blocker| {
+                   let mut chain = vec![blocker.to_string()];
+                   let mut current = blocker;
+                   while let Some(prev) = reached_from.get(current) {
+                       chain.push(prev.to_string());
+                       current = prev;
+                   }
+                   chain.reverse();
+                   Blocker { chain, origin: find(resolved, blocker).origin }
+               })
+               .collect())
+}
+
+/// Chains of enabled dependents ending in `option`, one per enabled direct dependent
+fn requiring_chains(option: &str, resolved: &[Resolved<'_>], graph: &Graph<&str, state::Complete>)
+    -> Result<Vec<Vec<String>>, Box<dyn error::Error>>
+{
+    let enabled_dependents = |opt: &str| -> Result<Vec<String>, Box<dyn error::Error>> {
+        Ok(graph.direct_dependents_of(&opt)?
+                .into_iter()
+                .filter(|d| is_active(find(resolved, d)))
+                .map(|d| d.to_string())
+                .collect())
+    };
+
+    let mut chains = vec![];
+    for dependent in enabled_dependents(option)? {
+        let mut chain = vec![option.to_string(), dependent.clone()];
+        let mut current = dependent;
+        while let Some(next) = enabled_dependents(&current)?.into_iter().find(|d| !chain.contains(d)) {
+            chain.push(next.clone());
+            current = next;
+        }
+        chain.reverse();
+        chains.push(chain);
+    }
+    Ok(chains)
+}
+
+/// Explain the value of `option` in the resolved config
+pub fn explain(option: &str, resolved: &[Resolved<'_>], graph: &Graph<&str, state::Complete>, log: &Logger)
+    -> Result<Explanation, Box<dyn error::Error>>
+{
+    let res = match resolved.iter().find(|r| r.entry.name == option) {
+        Some(res) => res,
+        None => return Err(format!("Invalid config option \"{}\"", option).into())
+    };
+
+    log.writeln(Verbosity::Lvl1, &format!("Explaining \"{}\"", option));
+    let blocked_by = blocking_chains(option, resolved, graph)?;
+    let required_by = match res.effective_value() {
+        Some("n") | None => vec![],
+        Some(_) => requiring_chains(option, resolved, graph)?
+    };
+
+    Ok(Explanation {
+        option: option.to_string(),
+        value: res.effective_value().map(|v| v.to_string()),
+        origin: res.origin,
+        required_by,
+        blocked_by
+    })
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.value {
+            Some(value) => writeln!(f, "{} = {}", self.option, value)?,
+            None => writeln!(f, "{} is disabled", self.option)?
+        };
+        match self.origin {
+            Origin::Explicit => writeln!(f, "  Set explicitly in the config")?,
+            Origin::Default => writeln!(f, "  Not set, using the default from the specification")?,
+            Origin::Disabled => writeln!(f, "  Not set, and unavailable because a dependency is disabled")?
+        };
+
+        if !self.required_by.is_empty() {
+            writeln!(f, "  Required by enabled options, disabling it would disable them as well:")?;
+            for chain in &self.required_by {
+                writeln!(f, "    {}", chain.join(" -> "))?;
+            }
+        }
+
+        if !self.blocked_by.is_empty() {
+            writeln!(f, "  Blocked by disabled dependencies, enabling it requires enabling:")?;
+            for blocker in &self.blocked_by {
+                let cause = match blocker.origin {
+                    Origin::Explicit => "disabled explicitly",
+                    Origin::Default => "disabled by default",
+                    Origin::Disabled => "disabled"
+                };
+                writeln!(f, "    {} ({})", blocker.chain.join(" -> "), cause)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Print why `option` has its current value in the config at `path`
+pub fn why(option: &str, path: &path::PathBuf, entries: &[ConfigEntry],
+           graph: &Graph<&str, state::Complete>, log: &Logger)
+    -> Result<(), Box<dyn error::Error>>
+{
+    let resolved = generate::resolve_config(path, entries, graph, log)?;
+    print!("{}", explain(option, &resolved, graph, log)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::why::*;
+    use crate::test_util::{entry, kvpairs};
+    use crate::{parse, EntryType, Switch};
+
+    fn entries() -> Vec<ConfigEntry> {
+        vec![
+            entry("CONFIG_BASE", &[], EntryType::Switch(Switch::Yes)),
+            entry("CONFIG_NET", &["CONFIG_BASE"], EntryType::Switch(Switch::No)),
+            entry("CONFIG_NET_PORT", &["CONFIG_NET"], EntryType::Int(80)),
+            entry("CONFIG_NET_TLS", &["CONFIG_NET"], EntryType::Switch(Switch::Yes))
+        ]
+    }
+
+    #[test]
+    fn explains_blocked_option() -> Result<(), Box<dyn error::Error>> {
+        let entries = entries();
+        let graph = parse::spec_graph(&entries)?;
+        let log = Logger::new(0);
+        let resolved = generate::resolve(&kvpairs(&[("CONFIG_BASE", "y")]), &entries, &graph, &log)?;
+        let explanation = explain("CONFIG_NET_PORT", &resolved, &graph, &log)?;
+        assert_eq!(explanation.origin, Origin::Disabled);
+        assert_eq!(explanation.blocked_by, vec![Blocker {
+            chain: vec!["CONFIG_NET_PORT".to_string(), "CONFIG_NET".to_string()],
+            origin: Origin::Default
+        }]);
+        Ok(())
+    }
+
+    #[test]
+    fn explains_required_option() -> Result<(), Box<dyn error::Error>> {
+        let entries = entries();
+        let graph = parse::spec_graph(&entries)?;
+        let log = Logger::new(0);
+        let config = kvpairs(&[("CONFIG_NET", "y"), ("CONFIG_NET_TLS", "n")]);
+        let resolved = generate::resolve(&config, &entries, &graph, &log)?;
+        let explanation = explain("CONFIG_BASE", &resolved, &graph, &log)?;
+        assert_eq!(explanation.origin, Origin::Default);
+        assert_eq!(explanation.required_by, vec![vec!["CONFIG_NET_PORT".to_string(), "CONFIG_NET".to_string(),
+                                                      "CONFIG_BASE".to_string()]]);
+        assert!(explanation.blocked_by.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn explains_every_blocker() -> Result<(), Box<dyn error::Error>> {
+        let mut entries = entries();
+        entries.push(entry("CONFIG_USB", &[], EntryType::Switch(Switch::Yes)));
+        entries.push(entry("CONFIG_MODEM", &["CONFIG_NET_TLS", "CONFIG_USB"], EntryType::Switch(Switch::Yes)));
+        let graph = parse::spec_graph(&entries)?;
+        let log = Logger::new(0);
+        let resolved = generate::resolve(&kvpairs(&[("CONFIG_USB", "n")]), &entries, &graph, &log)?;
+        let explanation = explain("CONFIG_MODEM", &resolved, &graph, &log)?;
+        assert_eq!(explanation.to_string(), "CONFIG_MODEM = n\n  \
+                                             Not set, and unavailable because a dependency is disabled\n  \
+                                             Blocked by disabled dependencies, enabling it requires enabling:\n    \
+                                             CONFIG_MODEM -> CONFIG_USB (disabled explicitly)\n    \
+                                             CONFIG_MODEM -> CONFIG_NET_TLS -> CONFIG_NET (disabled by default)\n");
+        Ok(())
+    }
+
+    #[test]
+    fn reports_one_chain_per_blocker() -> Result<(), Box<dyn error::Error>> {
+        // Diamonds double the number of paths at every level
+        let mut entries = vec![entry("OPT0", &[], EntryType::Switch(Switch::No))];
+        for i in 1..=40 {
+            let prev = format!("OPT{}", i - 1);
+            let (left, right) = (format!("LEFT{}", i), format!("RIGHT{}", i));
+            entries.push(entry(&left, &[&prev], EntryType::Switch(Switch::Yes)));
+            entries.push(entry(&right, &[&prev], EntryType::Switch(Switch::Yes)));
+            entries.push(entry(&format!("OPT{}", i), &[&left, &right], EntryType::Switch(Switch::Yes)));
+        }
+        let graph = parse::spec_graph(&entries)?;
+        let log = Logger::new(0);
+        let resolved = generate::resolve(&[], &entries, &graph, &log)?;
+        let explanation = explain("OPT40", &resolved, &graph, &log)?;
+        assert_eq!(explanation.blocked_by.len(), 1);
+        assert_eq!(explanation.blocked_by[0].chain.len(), 81);
+        assert_eq!(explanation.blocked_by[0].chain.last().map(|s| s.as_str()), Some("OPT0"));
+        Ok(())
+    }
+}
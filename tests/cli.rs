@@ -0,0 +1,30 @@
+use std::{env, fs, path, process};
+
+fn temp_dir(name: &str) -> path::PathBuf {
+    let dir = env::temp_dir().join(format!("conftool-cli-{}-{}", process::id(), name));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn spec(entries: &str) -> String {
+    format!("{{\"entries\": [{}]}}", entries)
+}
+
+#[test]
+fn spec_diff_needs_no_default_specification() {
+    let dir = temp_dir("spec-diff");
+    let base = r#"{"name": "CONFIG_BASE", "depends": [], "entrytype": "switch", "default": "y", "help": ""}"#;
+    let net = r#"{"name": "CONFIG_NET", "depends": ["CONFIG_BASE"], "entrytype": "switch", "default": "n", "help": ""}"#;
+    fs::write(dir.join("old.json"), spec(base)).unwrap();
+    fs::write(dir.join("new.json"), spec(&format!("{}, {}", base, net))).unwrap();
+    assert!(!dir.join(".conftool.json").exists());
+
+    let output = process::Command::new(env!("CARGO_BIN_EXE_conftool"))
+        .current_dir(&dir)
+        .args(["spec-diff", "old.json", "new.json"])
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("CONFIG_NET"));
+}